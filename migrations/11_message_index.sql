@@ -0,0 +1,2 @@
+create index "message_chat_id_created_at_id_idx"
+    on "message" ("chat_id", "created_at", "id");
@@ -0,0 +1,2 @@
+create index "user_read_message_message_id_idx"
+    on "user_read_message" ("message_id");
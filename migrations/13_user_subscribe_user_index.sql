@@ -0,0 +1,2 @@
+create index "user_subscribe_user_to_user_id_idx"
+    on "user_subscribe_user" ("to_user_id", "created_at");
@@ -0,0 +1,86 @@
+-- Blocking is enforced by the database, so it can not be bypassed by any query.
+-- Violations are raised as the "user_blocked" constraint and turned into `403 Forbidden` by the api.
+
+create or replace function is_blocked(blocker uuid, blocked uuid)
+    returns boolean as
+$$
+select exists (
+    select 1 from "user_block_user"
+    where "from_user_id" = blocker
+    and "to_user_id" = blocked
+);
+$$ language sql stable;
+
+create or replace function raise_user_blocked()
+    returns void as
+$$
+begin
+    raise exception 'user is blocked'
+        using errcode = 'check_violation', constraint = 'user_blocked';
+end;
+$$ language plpgsql;
+
+-- blocked user can not follow the blocker
+create or replace function check_block_subscribe()
+    returns trigger as
+$$
+begin
+    if is_blocked(NEW.to_user_id, NEW.from_user_id) then
+        perform raise_user_blocked();
+    end if;
+    return NEW;
+end;
+$$ language plpgsql;
+
+create trigger check_block_subscribe_trigger
+before insert on "user_subscribe_user"
+for each row
+execute procedure check_block_subscribe();
+
+-- blocked user can not join a private chat with the blocker
+create or replace function check_block_chat_user()
+    returns trigger as
+$$
+begin
+    if exists (
+        select 1 from "chat" c
+        join "chat_user" cu on cu."chat_id" = c."id"
+        where c."id" = NEW.chat_id
+        and c."type" = 'private'
+        and cu."user_id" != NEW.user_id
+        and is_blocked(cu."user_id", NEW.user_id)
+    ) then
+        perform raise_user_blocked();
+    end if;
+    return NEW;
+end;
+$$ language plpgsql;
+
+create trigger check_block_chat_user_trigger
+after insert on "chat_user"
+for each row
+execute procedure check_block_chat_user();
+
+-- blocked user can not send messages to the blocker in a private chat
+create or replace function check_block_message()
+    returns trigger as
+$$
+begin
+    if exists (
+        select 1 from "chat" c
+        join "chat_user" cu on cu."chat_id" = c."id"
+        where c."id" = NEW.chat_id
+        and c."type" = 'private'
+        and cu."user_id" != NEW.sender_id
+        and is_blocked(cu."user_id", NEW.sender_id)
+    ) then
+        perform raise_user_blocked();
+    end if;
+    return NEW;
+end;
+$$ language plpgsql;
+
+create trigger check_block_message_trigger
+before insert on "message"
+for each row
+execute procedure check_block_message();
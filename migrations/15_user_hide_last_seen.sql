@@ -0,0 +1,3 @@
+-- Users can hide the time they were last seen online from others
+alter table "user"
+add column "hide_last_seen" boolean not null default false;
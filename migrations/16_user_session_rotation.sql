@@ -0,0 +1,23 @@
+-- Session id is the id of its current token pair, so it changes every time tokens are refreshed.
+-- Family id stays the same for the whole life of the session.
+alter table "user_session"
+add column "family_id" uuid;
+
+update "user_session"
+set "family_id" = "id";
+
+alter table "user_session"
+alter column "family_id" set not null;
+
+create index "user_session_family_id_idx"
+    on "user_session" ("family_id");
+
+-- Ids of token pairs that were already refreshed.
+-- Using one of them again means the refresh token was stolen.
+create table "user_session_rotated"
+(
+    "id" uuid primary key,
+    "family_id" uuid not null,
+    "user_id" uuid not null references "user" ("id") on delete cascade,
+    "rotated_at" timestamptz not null default now()
+);
@@ -0,0 +1,4 @@
+-- Set when user opens the link from verification e-mail,
+-- reset when user changes their e-mail
+alter table "user"
+add column "email_verified_at" timestamptz;
@@ -0,0 +1,12 @@
+-- One-time tokens for resetting forgotten passwords.
+-- Only hashes are stored, tokens themselves are sent by e-mail.
+create table "password_reset_token"
+(
+    "token_hash" text primary key,
+    "user_id" uuid not null references "user" ("id") on delete cascade,
+    "expires_at" timestamptz not null,
+    "created_at" timestamptz not null default now()
+);
+
+create index "password_reset_token_user_id_idx"
+    on "password_reset_token" ("user_id");
@@ -0,0 +1,22 @@
+-- TOTP two-factor authentication.
+-- It is only enabled after user confirms it with a valid code.
+create table "user_totp"
+(
+    "user_id" uuid primary key references "user" ("id") on delete cascade,
+    "secret" bytea not null,
+    -- time step of the last accepted code, so it can not be used twice
+    "last_used_step" bigint,
+    "enabled_at" timestamptz,
+    "created_at" timestamptz not null default now()
+);
+
+-- Codes which can be used instead of TOTP if the phone is lost.
+-- Every code can only be used once, only hashes are stored.
+create table "user_recovery_code"
+(
+    "code_hash" text primary key,
+    "user_id" uuid not null references "user" ("id") on delete cascade
+);
+
+create index "user_recovery_code_user_id_idx"
+    on "user_recovery_code" ("user_id");
@@ -0,0 +1,7 @@
+-- Usernames and e-mails are compared case-insensitively,
+-- so users can log in with any case.
+alter table "user"
+alter column "username" type text collate case_insensitive;
+
+alter table "user"
+alter column "email" type text collate case_insensitive;
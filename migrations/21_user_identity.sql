@@ -0,0 +1,16 @@
+-- Accounts of external identity providers (OpenID Connect) linked to users.
+-- Subject is the id of the account, it is only unique inside of its provider.
+create table "user_identity"
+(
+    "provider" text not null,
+    "subject" text not null,
+    "user_id" uuid not null references "user" ("id") on delete cascade,
+    "email" text,
+    "created_at" timestamptz not null default now(),
+    primary key ("provider", "subject"),
+    unique ("user_id", "provider")
+);
+
+-- Users registered with a provider do not have a password until they set one
+alter table "user"
+alter column "password_hash" drop not null;
@@ -0,0 +1,34 @@
+-- Roles give users permissions, e.g. to manage other users or moderate chats.
+-- Permissions are checked by the api, roles only group them.
+create table "role"
+(
+    "name" text primary key,
+    "description" text not null default ''
+);
+
+create table "role_permission"
+(
+    "role" text not null references "role" ("name") on delete cascade,
+    "permission" text not null,
+    primary key ("role", "permission")
+);
+
+create table "user_role"
+(
+    "user_id" uuid not null references "user" ("id") on delete cascade,
+    "role" text not null references "role" ("name") on delete cascade,
+    -- admin who assigned the role
+    "assigned_by" uuid references "user" ("id") on delete set null,
+    "created_at" timestamptz not null default now(),
+    primary key ("user_id", "role")
+);
+
+insert into "role" ("name", "description")
+values ('admin', 'Manages users and their roles'),
+       ('moderator', 'Moderates messages');
+
+insert into "role_permission" ("role", "permission")
+values ('admin', 'roles.manage'),
+       ('admin', 'users.manage'),
+       ('admin', 'messages.moderate'),
+       ('moderator', 'messages.moderate');
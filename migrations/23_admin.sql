@@ -0,0 +1,27 @@
+-- Suspended users can not log in until "suspended_until", or ever if it is null.
+create table "user_suspension"
+(
+    "user_id" uuid primary key references "user" ("id") on delete cascade,
+    "reason" text not null,
+    "suspended_until" timestamptz,
+    "suspended_by" uuid references "user" ("id") on delete set null,
+    "created_at" timestamptz not null default now()
+);
+
+-- Every action of admins on users.
+-- Ids are not foreign keys, so the log is kept after users are deleted.
+create table "admin_audit_log"
+(
+    "id" uuid primary key default gen_random_uuid(),
+    "admin_id" uuid not null,
+    "action" text not null,
+    "target_user_id" uuid,
+    "details" text not null default '',
+    "created_at" timestamptz not null default now()
+);
+
+create index "admin_audit_log_created_at_idx"
+    on "admin_audit_log" ("created_at");
+
+create index "admin_audit_log_target_user_id_idx"
+    on "admin_audit_log" ("target_user_id", "created_at");
@@ -0,0 +1,10 @@
+-- Recovery codes of different users could have the same hash,
+-- they only have to be unique for one user.
+alter table "user_recovery_code"
+    drop constraint "user_recovery_code_pkey";
+
+alter table "user_recovery_code"
+    add primary key ("user_id", "code_hash");
+
+-- covered by the primary key now
+drop index "user_recovery_code_user_id_idx";
@@ -0,0 +1,4 @@
+-- When the user logged in, it is kept when tokens of the session are refreshed.
+-- Some actions need a recent login.
+alter table "user_session"
+    add column "created_at" timestamptz not null default now();
@@ -0,0 +1,10 @@
+-- Comparisons with case insensitive collation are always true,
+-- so lowercase is checked with the default one.
+alter table "user"
+    drop constraint "user_check";
+
+alter table "user"
+    add constraint "user_check" check (
+        "username" collate "default" = lower("username") collate "default"
+        and "email" collate "default" = lower("email") collate "default"
+    );
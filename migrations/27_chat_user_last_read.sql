@@ -0,0 +1,46 @@
+-- Every member only remembers the last message they have read,
+-- messages after it (except their own) are unread.
+alter table "chat_user"
+    add column "last_read_message_id" uuid references "message" ("id") on delete set null,
+    add column "last_read_at" timestamptz;
+
+update "chat_user" cu
+set
+    "last_read_message_id" = latest."message_id",
+    "last_read_at" = latest."created_at"
+from (
+    select distinct on (urm."user_id", m."chat_id")
+        urm."user_id", m."chat_id", urm."message_id", urm."created_at"
+    from "user_read_message" urm
+    join "message" m on m."id" = urm."message_id"
+    order by urm."user_id", m."chat_id", m."created_at" desc, m."id" desc
+) latest
+where cu."user_id" = latest."user_id"
+and cu."chat_id" = latest."chat_id";
+
+drop table "user_read_message";
+
+-- when the last read message is deleted, the one before it becomes the last read,
+-- so nothing older turns unread again
+create or replace function move_last_read()
+    returns trigger as
+$$
+begin
+    update "chat_user"
+    set "last_read_message_id" = (
+        select m."id" from "message" m
+        where m."chat_id" = OLD.chat_id
+        and (m."created_at", m."id") < (OLD.created_at, OLD.id)
+        order by m."created_at" desc, m."id" desc
+        limit 1
+    )
+    where "chat_id" = OLD.chat_id
+    and "last_read_message_id" = OLD.id;
+    return OLD;
+end;
+$$ language plpgsql;
+
+create trigger move_last_read_trigger
+before delete on "message"
+for each row
+execute procedure move_last_read();
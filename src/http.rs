@@ -4,15 +4,18 @@
 mod error;
 mod extractors;
 mod context;
+mod rate_limit;
 
 pub use error::*;
 pub use extractors::*;
 pub use context::*;
+pub use rate_limit::*;
 
 pub mod routers;
 
-// Some stuff I did not finish
-trait HttpErrorContext<T> {
+/// Replaces any error (or `None`) with the given [HttpError].
+/// Useful when the original error does not matter to the client, e.g. a missing row.
+pub trait HttpErrorContext<T> {
     fn http_context(self, error: HttpError) -> HttpResult<T>;
 }
 
@@ -1,12 +1,11 @@
 //! Definition and initialization of the shared HTTP context.
 
-use std::sync::Arc;
-use tokio::sync::Mutex;
-
 use sqlx::PgPool;
 use redis::aio::ConnectionManager;
 use reqwest::Client;
 
+use crate::logic::{event::{self, EventSender}, session::{self, LastActive}};
+
 /// # Shared HTTP context
 /// Or "application state".
 /// Includes connections to databases and everything that should only be initialized once
@@ -15,10 +14,14 @@ use reqwest::Client;
 pub struct HttpContext {
     /// Postgres pool
     pub pool: PgPool,
-    /// Redis pool
-    pub redis: Arc<Mutex<ConnectionManager>>,
+    /// Redis connection, clones share it, so it is cloned instead of being locked
+    pub redis: ConnectionManager,
     /// Reqwest client
-    pub client: Client
+    pub client: Client,
+    /// Events received from Redis, every WebSocket connection subscribes to it
+    pub events: EventSender,
+    /// Activity of sessions, written to the database in batches
+    pub last_active: LastActive
 }
 
 impl HttpContext {
@@ -35,15 +38,17 @@ impl HttpContext {
         let redis_client = redis::Client::open(redis_url)
             .expect("failed to create redis client");
 
+        let events = event::spawn_listener(redis_client.clone());
+
         let redis = ConnectionManager::new(redis_client).await
             .expect("failed to create redis connection");
 
-        let redis = Arc::new(Mutex::new(redis));
-
         let client = Client::new();
 
+        let last_active = session::spawn_last_active_writer(pool.clone());
+
         Self {
-            pool, redis, client
+            pool, redis, client, events, last_active
         }
     }
 }
\ No newline at end of file
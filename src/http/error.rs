@@ -1,7 +1,7 @@
 //! Error type definition and implementation of `IntoResponse`
 
 use axum::body::Body;
-use axum::http::header::WWW_AUTHENTICATE;
+use axum::http::header::{RETRY_AFTER, WWW_AUTHENTICATE};
 use axum::http::{HeaderMap, HeaderValue, Response, StatusCode};
 use axum::response::IntoResponse;
 use axum::Json;
@@ -40,7 +40,6 @@ pub enum HttpError {
 
     /// Return `403 Forbidden`
     #[error("user may not perform that action")]
-    #[allow(unused)]
     Forbidden,
 
     /// Return `404 Not Found`
@@ -48,16 +47,26 @@ pub enum HttpError {
     #[allow(unused)]
     NotFound(String),
 
+    /// Return `413 Payload Too Large`
+    #[error("request body is too large")]
+    PayloadTooLarge(String),
+
     /// Return `422 Unprocessable Entity`
     #[error("error in the request body")]
-    #[allow(unused)]
     UnprocessableEntity {
         errors: HashMap<Cow<'static, str>, Vec<Cow<'static, str>>>,
     },
 
+    /// Return `429 Too Many Requests` with `Retry-After` header
+    #[error("too many requests")]
+    TooManyRequests {
+        /// Seconds until the next request is allowed
+        retry_after: u64,
+    },
+
     /// Automatically return `500 Internal Server Error` on a `sqlx::Error`.
     #[error("an error occurred with the database")]
-    Sqlx(#[from] sqlx::Error),
+    Sqlx(sqlx::Error),
 
     /// Automatically return `500 Internal Server Error` on a `redis::RedisError`.
     #[error("an error occurred with the database")]
@@ -76,8 +85,15 @@ impl HttpError {
     pub fn not_found(path: impl Into<String>) -> Self {
         Self::NotFound(path.into())
     }
+
+    pub fn payload_too_large(message: impl Into<String>) -> Self {
+        Self::PayloadTooLarge(message.into())
+    }
     
-    #[allow(unused)]
+    pub fn too_many_requests(retry_after: u64) -> Self {
+        Self::TooManyRequests { retry_after }
+    }
+
     pub fn unprocessable_entity<K, V>(errors: impl IntoIterator<Item = (K, V)>) -> Self
     where
         K: Into<Cow<'static, str>>,
@@ -101,7 +117,9 @@ impl HttpError {
             Self::Unauthorized => StatusCode::UNAUTHORIZED,
             Self::Forbidden => StatusCode::FORBIDDEN,
             Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
             Self::UnprocessableEntity { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::TooManyRequests { .. } => StatusCode::TOO_MANY_REQUESTS,
             Self::Sqlx(_) | Self::Redis(_) | Self::Anyhow(_) => StatusCode::INTERNAL_SERVER_ERROR
         }
     }
@@ -113,7 +131,9 @@ impl HttpError {
             Self::Unauthorized => "Unauthorized",
             Self::Forbidden => "Forbidden",
             Self::NotFound(_) => "Not Found",
+            Self::PayloadTooLarge(_) => "Payload Too Large",
             Self::UnprocessableEntity { .. } => "Unprocessable Entity",
+            Self::TooManyRequests { .. } => "Too Many Requests",
             Self::Sqlx(_) | Self::Redis(_) | Self::Anyhow(_) => "Internal Server Error"
         }.to_string()
     }
@@ -122,6 +142,10 @@ impl HttpError {
         match self {
             Self::BadRequest(ref message) => Some(message.clone()),
             Self::NotFound(ref message) => Some(message.clone()),
+            Self::PayloadTooLarge(ref message) => Some(message.clone()),
+            Self::TooManyRequests { retry_after } => {
+                Some(format!("Too many requests, try again in {retry_after} seconds"))
+            },
             Self::Validator(ref errors) => {
                 for &field_errors in errors.field_errors().values() {
                     for error in field_errors {
@@ -145,6 +169,21 @@ impl HttpError {
     }
 }
 
+/// Raised by database triggers when blocked user tries to interact with the blocker.
+/// Check `14_user_block_enforce.sql` migration.
+const USER_BLOCKED_CONSTRAINT: &str = "user_blocked";
+
+impl From<sqlx::Error> for HttpError {
+    fn from(e: sqlx::Error) -> Self {
+        match e {
+            sqlx::Error::Database(ref dbe) if dbe.constraint() == Some(USER_BLOCKED_CONSTRAINT) => {
+                Self::Forbidden
+            }
+            e => Self::Sqlx(e),
+        }
+    }
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 struct ResponseError {
@@ -171,6 +210,15 @@ impl IntoResponse for HttpError {
                     Json(response),
                 ).into_response();
             }
+            Self::TooManyRequests { retry_after } => {
+                return (
+                    status_code,
+                    [(RETRY_AFTER, HeaderValue::from(retry_after))]
+                        .into_iter()
+                        .collect::<HeaderMap>(),
+                    Json(response),
+                ).into_response();
+            }
             Self::Sqlx(ref e) => {
                 log::error!("SQLx error: {:?}", e);
             }
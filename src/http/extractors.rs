@@ -4,7 +4,9 @@
 mod auth_user;
 mod request_info;
 mod validated_json;
+mod require_role;
 
 pub use request_info::*;
 pub use auth_user::*;
-pub use validated_json::*;
\ No newline at end of file
+pub use validated_json::*;
+pub use require_role::*;
\ No newline at end of file
@@ -11,7 +11,6 @@ use axum::{
     }
 };
 use time::OffsetDateTime;
-use sqlx::PgPool;
 use uuid::Uuid;
 use async_trait::async_trait;
 use crate::{
@@ -20,13 +19,18 @@ use crate::{
         HttpError,
         HttpContext
     },
-    utils::tokens::Claims
+    logic::{presence, session},
+    utils::tokens::{Claims, Grants}
 };
 
 /// # User must be authenticated
 pub struct AuthUser {
     pub user_id: Uuid,
-    pub session_id: Uuid
+    pub session_id: Uuid,
+    /// Roles and permissions from the access token
+    pub grants: Grants,
+    /// Unix time when the access token expires
+    pub expires_at: i64
 }
 
 /// # User could be authenticated
@@ -36,7 +40,7 @@ const PREFIX: &str = "Bearer ";
 
 impl AuthUser {
     async fn from_authorization(
-        pool: &PgPool,
+        ctx: &HttpContext,
         auth_header: &HeaderValue
     ) -> HttpResult<Self> {
         let auth_header = auth_header.to_str().map_err(|_| {
@@ -49,9 +53,15 @@ impl AuthUser {
             return Err(HttpError::Unauthorized);
         }
 
-        let token = &auth_header[PREFIX.len()..];
+        Self::from_token(ctx, &auth_header[PREFIX.len()..]).await
+    }
 
-        let claims = Claims::parse(token)
+    /// Checks the access token without the `Bearer` prefix
+    pub async fn from_token(
+        ctx: &HttpContext,
+        token: &str
+    ) -> HttpResult<Self> {
+        let claims = Claims::parse_access(token)
             .map_err(|_| {
                 HttpError::Unauthorized
             })?;
@@ -61,37 +71,30 @@ impl AuthUser {
             return Err(HttpError::Unauthorized);
         }
 
-        if sqlx::query!(
-            r#"
-            SELECT COUNT(1)
-            FROM user_session
-            WHERE id = $1
-            AND user_id = $2
-            "#,
-            claims.jti,
-            claims.user_id
-        )
-        .fetch_one(pool)
-        .await?
-        .count != Some(1) {
+        let user = Self {
+            user_id: claims.user_id,
+            session_id: claims.jti,
+            grants: claims.grants,
+            expires_at: claims.exp
+        };
+
+        // also checks that the user is not suspended
+        if !session::is_active(ctx, &user).await? {
             return Err(HttpError::Unauthorized);
         }
 
-        sqlx::query!(
-            r#"
-            UPDATE user_session
-            SET last_active = $2
-            WHERE id = $1
-            "#,
-            claims.jti, OffsetDateTime::now_utc()
-        )
-            .execute(pool)
-            .await?;
-
-        Ok(Self {
-            user_id: claims.user_id,
-            session_id: claims.jti
-        })
+        session::touch(ctx, user.session_id);
+        presence::touch(ctx, user.user_id).await;
+
+        Ok(user)
+    }
+
+    pub fn has_role(&self, role: &str) -> bool {
+        self.grants.roles.iter().any(|r| r == role)
+    }
+
+    pub fn has_permission(&self, permission: &str) -> bool {
+        self.grants.permissions.iter().any(|p| p == permission)
     }
 }
 
@@ -122,7 +125,7 @@ where
             .get(AUTHORIZATION)
             .ok_or(HttpError::Unauthorized)?;
 
-        Self::from_authorization(&state.pool, auth_header).await
+        Self::from_authorization(&state, auth_header).await
     }
 }
 
@@ -140,7 +143,7 @@ where
 
         Ok(Self(
             if let Some(header) = req.headers.get(AUTHORIZATION) {
-                AuthUser::from_authorization(&state.pool, header).await.ok()
+                AuthUser::from_authorization(&state, header).await.ok()
             } else {
                 None
             }
@@ -9,10 +9,12 @@ use anyhow::Context;
 use async_trait::async_trait;
 use axum::{
     extract::{ConnectInfo, FromRequestParts},
-    http::{header::USER_AGENT, request::Parts},
+    http::{header::USER_AGENT, request::Parts, HeaderMap},
 };
+use ipnet::IpNet;
+use once_cell::sync::Lazy;
 use reqwest::Client;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 
 /// # Info about request
 /// ip address and user agent.
@@ -42,6 +44,39 @@ impl RequestInfo {
     }
 }
 
+/// Addresses of reverse proxies allowed to set `X-Real-IP`,
+/// comma separated ips or networks in `TRUSTED_PROXIES` env variable (`127.0.0.1,::1` by default)
+static TRUSTED_PROXIES: Lazy<Vec<IpNet>> = Lazy::new(|| {
+    std::env::var("TRUSTED_PROXIES")
+        .unwrap_or("127.0.0.1,::1".to_string())
+        .split(',')
+        .map(str::trim)
+        .filter(|proxy| !proxy.is_empty())
+        .map(|proxy| {
+            proxy
+                .parse::<IpNet>()
+                .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+                .unwrap_or_else(|_| panic!("failed to parse {proxy} in TRUSTED_PROXIES env variable"))
+        })
+        .collect()
+});
+
+/// Real ip is set by the reverse proxy.
+/// Header is only trusted from [TRUSTED_PROXIES], anyone else could put any ip there.
+pub fn client_ip(headers: &HeaderMap, connect_info: &SocketAddr) -> String {
+    let peer = connect_info.ip();
+    if !TRUSTED_PROXIES.iter().any(|proxy| proxy.contains(&peer)) {
+        return peer.to_string();
+    }
+
+    headers
+        .get("x-real-ip")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.trim().parse::<IpAddr>().ok())
+        .unwrap_or(peer)
+        .to_string()
+}
+
 #[async_trait]
 impl<B> FromRequestParts<B> for RequestInfo
 where
@@ -54,13 +89,7 @@ where
             .await
             .context("failed to get connect info from request")?;
 
-        let ip = req
-            .headers
-            .get("x-real-ip")
-            .map(|header| header.to_str().ok())
-            .flatten()
-            .map(|header| header.to_string())
-            .unwrap_or(connect_info.ip().to_string());
+        let ip = client_ip(&req.headers, &connect_info);
 
         let user_agent = req
             .headers
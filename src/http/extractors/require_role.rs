@@ -0,0 +1,97 @@
+//! Extractors of users with roles or permissions.
+//! They are read from the access token, check [Grants][crate::utils::tokens::Grants].
+//! Unauthenticated users get [HttpError::Unauthorized], others without the role [HttpError::Forbidden].
+
+use std::{marker::PhantomData, ops::Deref};
+use axum::{extract::FromRequestParts, http::request::Parts};
+use async_trait::async_trait;
+use crate::http::{AuthUser, HttpError};
+
+/// Role which is required by [RequireRole]
+pub trait Role {
+    /// Name in the `role` table
+    const NAME: &'static str;
+}
+
+/// Permission which is required by [RequirePermission]
+pub trait Permission {
+    /// Name in the `role_permission` table
+    const NAME: &'static str;
+}
+
+pub mod roles {
+    pub struct Admin;
+    impl super::Role for Admin {
+        const NAME: &'static str = "admin";
+    }
+}
+
+pub mod permissions {
+    pub struct ManageRoles;
+    impl super::Permission for ManageRoles {
+        const NAME: &'static str = "roles.manage";
+    }
+
+    pub struct ManageUsers;
+    impl super::Permission for ManageUsers {
+        const NAME: &'static str = "users.manage";
+    }
+}
+
+/// # User must have the role
+/// For example `RequireRole(user, _): RequireRole<Admin>`
+pub struct RequireRole<R: Role>(pub AuthUser, pub PhantomData<R>);
+
+/// # User must have the permission
+/// For example `RequirePermission(user, _): RequirePermission<ManageRoles>`
+pub struct RequirePermission<P: Permission>(pub AuthUser, pub PhantomData<P>);
+
+impl<R: Role> Deref for RequireRole<R> {
+    type Target = AuthUser;
+
+    fn deref(&self) -> &AuthUser {
+        &self.0
+    }
+}
+
+impl<P: Permission> Deref for RequirePermission<P> {
+    type Target = AuthUser;
+
+    fn deref(&self) -> &AuthUser {
+        &self.0
+    }
+}
+
+#[async_trait]
+impl<B, R> FromRequestParts<B> for RequireRole<R>
+where
+    B: Send + Sync,
+    R: Role
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(req: &mut Parts, state: &B) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(req, state).await?;
+        if !user.has_role(R::NAME) {
+            return Err(HttpError::Forbidden);
+        }
+        Ok(Self(user, PhantomData))
+    }
+}
+
+#[async_trait]
+impl<B, P> FromRequestParts<B> for RequirePermission<P>
+where
+    B: Send + Sync,
+    P: Permission
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(req: &mut Parts, state: &B) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(req, state).await?;
+        if !user.has_permission(P::NAME) {
+            return Err(HttpError::Forbidden);
+        }
+        Ok(Self(user, PhantomData))
+    }
+}
@@ -0,0 +1,105 @@
+//! Rate limiting of requests, stored in Redis, so it is shared by all server instances.
+//! Every ip address has a separate sliding window for each route.
+//!
+//! Limits are set by env variables in `requests/seconds` format:
+//! - `RATE_LIMIT` (`120/60` by default) - for all routes
+//! - `RATE_LIMIT_AUTH` (`10/60` by default) - for routes that could be used
+//!   to guess passwords or send e-mails
+
+use std::{net::SocketAddr, sync::Arc};
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request},
+    middleware::Next,
+    response::Response,
+    Extension,
+};
+use once_cell::sync::Lazy;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::http::{client_ip, HttpContext, HttpError, HttpResult};
+
+/// Routes with [AUTH_LIMIT]
+const AUTH_ROUTES: [&str; 7] = [
+    "/auth/login",
+    "/auth/login/2fa",
+    "/auth/oidc/callback",
+    "/auth/register",
+    "/auth/forgot-password",
+    "/auth/reset-password",
+    "/auth/verify-email/resend",
+];
+
+#[derive(Clone, Copy)]
+struct Limit {
+    requests: u64,
+    /// Milliseconds
+    window: u64,
+}
+
+fn parse_limit(name: &str, default: &str) -> Limit {
+    let value = std::env::var(name).unwrap_or(default.to_string());
+    let (requests, seconds) = value
+        .split_once('/')
+        .and_then(|(requests, seconds)| Some((requests.parse().ok()?, seconds.parse::<u64>().ok()?)))
+        .unwrap_or_else(|| panic!("failed to parse {name} env variable, expected `requests/seconds`"));
+
+    Limit { requests, window: seconds * 1000 }
+}
+
+static LIMIT: Lazy<Limit> = Lazy::new(|| parse_limit("RATE_LIMIT", "120/60"));
+static AUTH_LIMIT: Lazy<Limit> = Lazy::new(|| parse_limit("RATE_LIMIT_AUTH", "10/60"));
+
+/// Removes requests that left the window and adds the new one if the limit is not reached.
+/// Returns milliseconds until the next request is allowed, `0` if this one is allowed.
+static SLIDING_WINDOW: Lazy<redis::Script> = Lazy::new(|| redis::Script::new(r#"
+    local now = tonumber(ARGV[1])
+    local window = tonumber(ARGV[2])
+    local limit = tonumber(ARGV[3])
+    redis.call('ZREMRANGEBYSCORE', KEYS[1], 0, now - window)
+    if redis.call('ZCARD', KEYS[1]) >= limit then
+        local oldest = redis.call('ZRANGE', KEYS[1], 0, 0, 'WITHSCORES')
+        return tonumber(oldest[2]) + window - now
+    end
+    redis.call('ZADD', KEYS[1], now, ARGV[4])
+    redis.call('PEXPIRE', KEYS[1], window)
+    return 0
+"#));
+
+async fn wait_time(ctx: &HttpContext, key: String, limit: Limit) -> redis::RedisResult<u64> {
+    let now = (OffsetDateTime::now_utc().unix_timestamp_nanos() / 1_000_000) as u64;
+    SLIDING_WINDOW
+        .key(key)
+        .arg(now)
+        .arg(limit.window)
+        .arg(limit.requests)
+        .arg(Uuid::new_v4().to_string())
+        .invoke_async(&mut ctx.redis.clone())
+        .await
+}
+
+/// Middleware, must be added with `route_layer`, so the route is already matched.
+/// If Redis is unavailable, requests are let through.
+pub async fn rate_limit(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    path: MatchedPath,
+    request: Request,
+    next: Next,
+) -> HttpResult<Response> {
+    let path = path.as_str();
+    let limit = match AUTH_ROUTES.contains(&path) {
+        true => *AUTH_LIMIT,
+        false => *LIMIT,
+    };
+    let ip = client_ip(request.headers(), &addr);
+    let key = format!("rate_limit:{ip}:{} {path}", request.method());
+
+    match wait_time(&ctx, key, limit).await {
+        Ok(0) => {}
+        Ok(wait) => return Err(HttpError::too_many_requests(wait.div_ceil(1000))),
+        Err(e) => log::error!("failed to check rate limit: {e:?}"),
+    }
+
+    Ok(next.run(request).await)
+}
@@ -2,13 +2,20 @@
 //! Each router contains routes for a specific part of the API.
 //! The main router combines them all into big one.
 
-use axum::{Router, Extension};
+use axum::{middleware, Router, Extension};
 use tower_http::cors::CorsLayer;
 use std::sync::Arc;
-use crate::http::HttpContext;
+use crate::http::{rate_limit, HttpContext};
 mod fallback;
 mod health;
 mod auth;
+mod users;
+mod chats;
+mod messages;
+mod uploads;
+mod gateway;
+mod well_known;
+mod admin;
 
 /// The main router
 pub async fn main() -> Router {
@@ -16,6 +23,13 @@ pub async fn main() -> Router {
     Router::new()
         .nest("/health", health::router())
         .nest("/auth", auth::router())
+        .nest("/users", users::router())
+        .nest("/chats", chats::router())
+        .nest("/uploads", uploads::router())
+        .nest("/ws", gateway::router())
+        .nest("/admin", admin::router())
+        .nest("/.well-known", well_known::router())
+        .route_layer(middleware::from_fn(rate_limit))
         .fallback(fallback::handler_404)
         .layer(cors())
         .layer(Extension(context))
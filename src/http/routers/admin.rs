@@ -0,0 +1,150 @@
+use crate::{
+    http::{
+        extractors::{RequirePermission, RequireRole, ValidatedJson},
+        permissions::{ManageRoles, ManageUsers},
+        roles::Admin,
+        HttpContext, HttpResult,
+    },
+    logic::{admin, audit, role},
+    models::{
+        database_models::{AuditEntry, ManagedUser, Role, Session},
+        http_models::{AuditQuery, SuspendBody, UserSearchQuery},
+    },
+};
+use axum::{
+    extract::{Path, Query},
+    routing::{get, post, put},
+    Extension, Json, Router,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/roles", get(get_roles))
+        .route("/audit", get(get_audit))
+        .route("/users", get(search_users))
+        .route("/users/:user_id", get(get_user).delete(delete_user))
+        .route("/users/:user_id/sessions", get(get_sessions).delete(logout_user))
+        .route("/users/:user_id/suspension", put(suspend_user).delete(unsuspend_user))
+        .route("/users/:user_id/password-reset", post(reset_password))
+        .route("/users/:user_id/roles", get(get_user_roles))
+        .route("/users/:user_id/roles/:role", put(assign_role).delete(remove_role))
+}
+
+pub async fn get_roles(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    _: RequireRole<Admin>,
+) -> HttpResult<Json<Vec<Role>>> {
+    let response = role::list(&ctx).await?;
+    Ok(Json(response))
+}
+
+pub async fn get_user_roles(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    _: RequireRole<Admin>,
+    Path(user_id): Path<Uuid>,
+) -> HttpResult<Json<Vec<String>>> {
+    let response = role::of_user(&ctx, user_id).await?;
+    Ok(Json(response))
+}
+
+pub async fn assign_role(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    RequirePermission(admin, _): RequirePermission<ManageRoles>,
+    Path((user_id, role)): Path<(Uuid, String)>,
+) -> HttpResult<()> {
+    role::assign(&ctx, admin.user_id, user_id, &role).await?;
+    Ok(())
+}
+
+pub async fn remove_role(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    RequirePermission(admin, _): RequirePermission<ManageRoles>,
+    Path((user_id, role)): Path<(Uuid, String)>,
+) -> HttpResult<()> {
+    role::remove(&ctx, admin.user_id, user_id, &role).await?;
+    Ok(())
+}
+
+pub async fn get_audit(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    _: RequirePermission<ManageUsers>,
+    Query(query): Query<AuditQuery>,
+) -> HttpResult<Json<Vec<AuditEntry>>> {
+    let response = audit::list(&ctx, query).await?;
+    Ok(Json(response))
+}
+
+pub async fn search_users(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    RequirePermission(admin, _): RequirePermission<ManageUsers>,
+    Query(query): Query<UserSearchQuery>,
+) -> HttpResult<Json<Vec<ManagedUser>>> {
+    let response = admin::search(&ctx, admin, query).await?;
+    Ok(Json(response))
+}
+
+pub async fn get_user(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    RequirePermission(admin, _): RequirePermission<ManageUsers>,
+    Path(user_id): Path<Uuid>,
+) -> HttpResult<Json<ManagedUser>> {
+    let response = admin::get(&ctx, admin, user_id).await?;
+    Ok(Json(response))
+}
+
+pub async fn delete_user(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    RequirePermission(admin, _): RequirePermission<ManageUsers>,
+    Path(user_id): Path<Uuid>,
+) -> HttpResult<()> {
+    admin::delete(&ctx, admin, user_id).await?;
+    Ok(())
+}
+
+pub async fn get_sessions(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    RequirePermission(admin, _): RequirePermission<ManageUsers>,
+    Path(user_id): Path<Uuid>,
+) -> HttpResult<Json<Vec<Session>>> {
+    let response = admin::sessions(&ctx, admin, user_id).await?;
+    Ok(Json(response))
+}
+
+pub async fn logout_user(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    RequirePermission(admin, _): RequirePermission<ManageUsers>,
+    Path(user_id): Path<Uuid>,
+) -> HttpResult<()> {
+    admin::logout(&ctx, admin, user_id).await?;
+    Ok(())
+}
+
+pub async fn suspend_user(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    RequirePermission(admin, _): RequirePermission<ManageUsers>,
+    Path(user_id): Path<Uuid>,
+    ValidatedJson(body): ValidatedJson<SuspendBody>,
+) -> HttpResult<Json<ManagedUser>> {
+    let response = admin::suspend(&ctx, admin, user_id, body).await?;
+    Ok(Json(response))
+}
+
+pub async fn unsuspend_user(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    RequirePermission(admin, _): RequirePermission<ManageUsers>,
+    Path(user_id): Path<Uuid>,
+) -> HttpResult<Json<ManagedUser>> {
+    let response = admin::unsuspend(&ctx, admin, user_id).await?;
+    Ok(Json(response))
+}
+
+pub async fn reset_password(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    RequirePermission(admin, _): RequirePermission<ManageUsers>,
+    Path(user_id): Path<Uuid>,
+) -> HttpResult<()> {
+    admin::reset_password(&ctx, admin, user_id).await?;
+    Ok(())
+}
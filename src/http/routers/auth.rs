@@ -3,30 +3,66 @@ use crate::{
         extractors::{AuthUser, RequestInfo, ValidatedJson},
         HttpContext, HttpResult,
     },
-    logic::auth,
-    models::http_models::{AuthResponse, LoginBody, RefreshBody, RegisterBody},
+    logic::{auth, oidc, password_reset, session, two_factor, verification},
+    models::{
+        database_models::{Session, UserIdentity},
+        http_models::{
+            AuthResponse, AuthorizationUrl, ForgotPasswordBody, LoginBody, LoginMfaBody,
+            LoginResponse, OidcCallbackBody, RefreshBody, RegisterBody, ResetPasswordBody,
+            TwoFactorCodeBody, TwoFactorEnrollBody, TwoFactorEnrollment, VerifyEmailBody,
+        },
+    },
     utils::tokens::TokenPair,
 };
-use axum::{routing::post, Extension, Json, Router};
+use axum::{
+    extract::Path,
+    routing::{delete, get, post},
+    Extension, Json, Router,
+};
 use std::sync::Arc;
+use uuid::Uuid;
 
 pub fn router() -> Router {
     Router::new()
         .route("/login", post(login))
+        .route("/login/2fa", post(login_mfa))
         .route("/register", post(register))
         .route("/refresh", post(refresh))
         .route("/logout", post(logout))
+        .route("/verify-email", post(verify_email))
+        .route("/verify-email/resend", post(resend_verification))
+        .route("/forgot-password", post(forgot_password))
+        .route("/reset-password", post(reset_password))
+        .route("/2fa", delete(disable_two_factor))
+        .route("/2fa/enroll", post(enroll_two_factor))
+        .route("/2fa/confirm", post(confirm_two_factor))
+        .route("/sessions", get(get_sessions).delete(revoke_other_sessions))
+        .route("/sessions/:family_id", delete(revoke_session))
+        .route("/oidc", get(get_providers))
+        .route("/oidc/:provider", get(authorize_provider))
+        .route("/oidc/callback", post(login_provider))
+        .route("/identities", get(get_identities).post(link_identity))
+        .route("/identities/:provider", get(authorize_identity).delete(unlink_identity))
 }
 
 pub async fn login(
     Extension(ctx): Extension<Arc<HttpContext>>,
     info: RequestInfo,
     ValidatedJson(body): ValidatedJson<LoginBody>,
-) -> HttpResult<Json<AuthResponse>> {
+) -> HttpResult<Json<LoginResponse>> {
     let response = auth::login(&ctx, body, info).await?;
     Ok(Json(response))
 }
 
+pub async fn login_mfa(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    info: RequestInfo,
+    ValidatedJson(body): ValidatedJson<LoginMfaBody>,
+) -> HttpResult<Json<AuthResponse>> {
+    let response = auth::login_mfa(&ctx, body, info).await?;
+    Ok(Json(response))
+}
+
 pub async fn register(
     Extension(ctx): Extension<Arc<HttpContext>>,
     info: RequestInfo,
@@ -49,3 +85,143 @@ pub async fn logout(Extension(ctx): Extension<Arc<HttpContext>>, user: AuthUser)
     auth::logout(&ctx, user).await?;
     Ok(())
 }
+
+pub async fn verify_email(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    ValidatedJson(body): ValidatedJson<VerifyEmailBody>,
+) -> HttpResult<()> {
+    verification::verify(&ctx, body).await?;
+    Ok(())
+}
+
+pub async fn resend_verification(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+) -> HttpResult<()> {
+    verification::resend(&ctx, user).await?;
+    Ok(())
+}
+
+pub async fn forgot_password(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    ValidatedJson(body): ValidatedJson<ForgotPasswordBody>,
+) -> HttpResult<()> {
+    password_reset::forgot(&ctx, body).await?;
+    Ok(())
+}
+
+pub async fn reset_password(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    ValidatedJson(body): ValidatedJson<ResetPasswordBody>,
+) -> HttpResult<()> {
+    password_reset::reset(&ctx, body).await?;
+    Ok(())
+}
+
+pub async fn enroll_two_factor(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    ValidatedJson(body): ValidatedJson<TwoFactorEnrollBody>,
+) -> HttpResult<Json<TwoFactorEnrollment>> {
+    let response = two_factor::enroll(&ctx, user, body).await?;
+    Ok(Json(response))
+}
+
+pub async fn confirm_two_factor(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    ValidatedJson(body): ValidatedJson<TwoFactorCodeBody>,
+) -> HttpResult<()> {
+    two_factor::confirm(&ctx, user, body).await?;
+    Ok(())
+}
+
+pub async fn disable_two_factor(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    ValidatedJson(body): ValidatedJson<TwoFactorCodeBody>,
+) -> HttpResult<()> {
+    two_factor::disable(&ctx, user, body).await?;
+    Ok(())
+}
+
+pub async fn get_sessions(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+) -> HttpResult<Json<Vec<Session>>> {
+    let response = session::list(&ctx, user).await?;
+    Ok(Json(response))
+}
+
+pub async fn revoke_session(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path(family_id): Path<Uuid>,
+) -> HttpResult<()> {
+    session::revoke(&ctx, user, family_id).await?;
+    Ok(())
+}
+
+pub async fn revoke_other_sessions(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+) -> HttpResult<()> {
+    session::revoke_others(&ctx, &user).await?;
+    Ok(())
+}
+
+pub async fn get_providers() -> Json<Vec<String>> {
+    Json(oidc::providers())
+}
+
+pub async fn authorize_provider(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    Path(provider): Path<String>,
+) -> HttpResult<Json<AuthorizationUrl>> {
+    let response = oidc::authorize(&ctx, &provider).await?;
+    Ok(Json(response))
+}
+
+pub async fn login_provider(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    info: RequestInfo,
+    ValidatedJson(body): ValidatedJson<OidcCallbackBody>,
+) -> HttpResult<Json<LoginResponse>> {
+    let response = oidc::login(&ctx, body, info).await?;
+    Ok(Json(response))
+}
+
+pub async fn get_identities(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+) -> HttpResult<Json<Vec<UserIdentity>>> {
+    let response = oidc::list(&ctx, user).await?;
+    Ok(Json(response))
+}
+
+pub async fn authorize_identity(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path(provider): Path<String>,
+) -> HttpResult<Json<AuthorizationUrl>> {
+    let response = oidc::authorize_link(&ctx, user, &provider).await?;
+    Ok(Json(response))
+}
+
+pub async fn link_identity(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    ValidatedJson(body): ValidatedJson<OidcCallbackBody>,
+) -> HttpResult<Json<UserIdentity>> {
+    let response = oidc::link(&ctx, user, body).await?;
+    Ok(Json(response))
+}
+
+pub async fn unlink_identity(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path(provider): Path<String>,
+) -> HttpResult<()> {
+    oidc::unlink(&ctx, user, &provider).await?;
+    Ok(())
+}
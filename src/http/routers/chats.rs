@@ -0,0 +1,96 @@
+use crate::{
+    http::{
+        extractors::{AuthUser, ValidatedJson},
+        HttpContext, HttpResult,
+    },
+    logic::chat,
+    models::{
+        database_models::{Chat, ChatListItem},
+        http_models::{CreateGroupChatBody, EditChatBody, OpenPrivateChatBody},
+    },
+    utils::storage,
+};
+use axum::{
+    extract::{DefaultBodyLimit, Multipart, Path},
+    routing::{get, post, put},
+    Extension, Json, Router,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/", get(list_chats).post(create_group))
+        .route("/private", post(open_private))
+        .route("/saved", get(get_saved))
+        .route("/:chat_id", get(get_chat).patch(edit_chat))
+        .route(
+            "/:chat_id/image",
+            // size of the image is checked while it is being read
+            put(set_image).layer(DefaultBodyLimit::max(storage::body_limit(1)))
+        )
+        .nest("/:chat_id/messages", super::messages::router())
+}
+
+pub async fn list_chats(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+) -> HttpResult<Json<Vec<ChatListItem>>> {
+    let response = chat::list(&ctx, user).await?;
+    Ok(Json(response))
+}
+
+pub async fn create_group(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    ValidatedJson(body): ValidatedJson<CreateGroupChatBody>,
+) -> HttpResult<Json<Chat>> {
+    let response = chat::create_group(&ctx, user, body).await?;
+    Ok(Json(response))
+}
+
+pub async fn open_private(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    ValidatedJson(body): ValidatedJson<OpenPrivateChatBody>,
+) -> HttpResult<Json<Chat>> {
+    let response = chat::open_private(&ctx, user, body).await?;
+    Ok(Json(response))
+}
+
+pub async fn get_saved(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+) -> HttpResult<Json<Chat>> {
+    let response = chat::get_saved(&ctx, user).await?;
+    Ok(Json(response))
+}
+
+pub async fn get_chat(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path(chat_id): Path<Uuid>,
+) -> HttpResult<Json<Chat>> {
+    let response = chat::get(&ctx, user, chat_id).await?;
+    Ok(Json(response))
+}
+
+pub async fn edit_chat(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path(chat_id): Path<Uuid>,
+    ValidatedJson(body): ValidatedJson<EditChatBody>,
+) -> HttpResult<Json<Chat>> {
+    let response = chat::edit(&ctx, user, chat_id, body).await?;
+    Ok(Json(response))
+}
+
+pub async fn set_image(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path(chat_id): Path<Uuid>,
+    multipart: Multipart,
+) -> HttpResult<Json<Chat>> {
+    let response = chat::set_image(&ctx, user, chat_id, multipart).await?;
+    Ok(Json(response))
+}
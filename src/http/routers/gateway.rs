@@ -0,0 +1,19 @@
+use crate::{
+    http::{extractors::MaybeAuthUser, HttpContext},
+    logic::gateway,
+};
+use axum::{extract::WebSocketUpgrade, response::Response, routing::get, Extension, Router};
+use std::sync::Arc;
+
+pub fn router() -> Router {
+    Router::new().route("/", get(connect))
+}
+
+/// Without the `Authorization` header, the first event must authenticate the connection
+pub async fn connect(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    MaybeAuthUser(user): MaybeAuthUser,
+    ws: WebSocketUpgrade,
+) -> Response {
+    ws.on_upgrade(move |socket| gateway::handle(ctx, user, socket))
+}
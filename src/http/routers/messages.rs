@@ -0,0 +1,84 @@
+use crate::{
+    http::{
+        extractors::{AuthUser, ValidatedJson},
+        HttpContext, HttpResult,
+    },
+    logic::message,
+    models::{
+        database_models::{Message, MessageRead},
+        http_models::{EditMessageBody, MessagesQuery, SendMessageBody},
+    },
+};
+use axum::{
+    extract::{Path, Query},
+    routing::{get, patch, post},
+    Extension, Json, Router,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Nested inside of the chats router, so every route has `chat_id` in its path
+pub fn router() -> Router {
+    Router::new()
+        .route("/", get(list_messages).post(send_message))
+        .route("/:message_id", patch(edit_message).delete(delete_message))
+        .route("/:message_id/read", post(mark_read))
+        .route("/:message_id/reads", get(read_by))
+}
+
+pub async fn list_messages(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path(chat_id): Path<Uuid>,
+    Query(query): Query<MessagesQuery>,
+) -> HttpResult<Json<Vec<Message>>> {
+    let response = message::list(&ctx, user, chat_id, query).await?;
+    Ok(Json(response))
+}
+
+pub async fn send_message(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path(chat_id): Path<Uuid>,
+    ValidatedJson(body): ValidatedJson<SendMessageBody>,
+) -> HttpResult<Json<Message>> {
+    let response = message::send(&ctx, user, chat_id, body).await?;
+    Ok(Json(response))
+}
+
+pub async fn edit_message(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path((chat_id, message_id)): Path<(Uuid, Uuid)>,
+    ValidatedJson(body): ValidatedJson<EditMessageBody>,
+) -> HttpResult<Json<Message>> {
+    let response = message::edit(&ctx, user, chat_id, message_id, body).await?;
+    Ok(Json(response))
+}
+
+pub async fn delete_message(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path((chat_id, message_id)): Path<(Uuid, Uuid)>,
+) -> HttpResult<()> {
+    message::delete(&ctx, user, chat_id, message_id).await?;
+    Ok(())
+}
+
+pub async fn mark_read(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path((chat_id, message_id)): Path<(Uuid, Uuid)>,
+) -> HttpResult<()> {
+    message::mark_read(&ctx, user, chat_id, message_id).await?;
+    Ok(())
+}
+
+pub async fn read_by(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path((chat_id, message_id)): Path<(Uuid, Uuid)>,
+) -> HttpResult<Json<Vec<MessageRead>>> {
+    let response = message::read_by(&ctx, user, chat_id, message_id).await?;
+    Ok(Json(response))
+}
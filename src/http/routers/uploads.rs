@@ -0,0 +1,43 @@
+use crate::{
+    http::{extractors::AuthUser, HttpContext, HttpResult},
+    logic::upload,
+    models::{database_models::Upload, http_models::UploadQuery},
+    utils::storage::{self, MAX_FILES},
+};
+use axum::{
+    extract::{DefaultBodyLimit, Multipart, Path, Query},
+    http::HeaderMap,
+    response::Response,
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use std::sync::Arc;
+use uuid::Uuid;
+
+pub fn router() -> Router {
+    Router::new()
+        .route(
+            "/",
+            // size of each file is checked while it is being saved
+            post(create_upload).layer(DefaultBodyLimit::max(storage::body_limit(*MAX_FILES)))
+        )
+        .route("/:upload_id", get(get_upload))
+}
+
+pub async fn create_upload(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    _user: AuthUser,
+    multipart: Multipart,
+) -> HttpResult<Json<Vec<Upload>>> {
+    let response = upload::create(&ctx, multipart).await?;
+    Ok(Json(response))
+}
+
+pub async fn get_upload(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    Path(upload_id): Path<Uuid>,
+    Query(query): Query<UploadQuery>,
+    headers: HeaderMap,
+) -> HttpResult<Response> {
+    upload::serve(&ctx, upload_id, query, &headers).await
+}
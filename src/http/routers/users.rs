@@ -0,0 +1,149 @@
+use crate::{
+    http::{
+        extractors::{AuthUser, MaybeAuthUser, ValidatedJson},
+        HttpContext, HttpResult,
+    },
+    logic::{block, follow, user},
+    utils::storage,
+    models::{
+        database_models::{MyUser, User},
+        http_models::{EditUserBody, FollowsQuery, UserProfile},
+    },
+};
+use axum::{
+    extract::{DefaultBodyLimit, Multipart, Path, Query},
+    routing::{get, post, put},
+    Extension, Json, Router,
+};
+use std::sync::Arc;
+
+pub fn router() -> Router {
+    Router::new()
+        .route("/me", get(get_me).patch(edit_me))
+        .route(
+            "/me/avatar",
+            // size of the image is checked while it is being read
+            put(set_avatar).delete(remove_avatar).layer(DefaultBodyLimit::max(storage::body_limit(1)))
+        )
+        .route("/me/blocked", get(get_blocked))
+        .route("/:username", get(get_user))
+        .route("/:username/block", post(block_user).delete(unblock_user))
+        .route("/:username/follow", post(follow_user).delete(unfollow_user))
+        .route("/:username/followers", get(get_followers))
+        .route("/:username/following", get(get_following))
+        .route("/:username/mutuals", get(get_mutuals))
+}
+
+pub async fn get_me(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+) -> HttpResult<Json<MyUser>> {
+    let response = user::get_me(&ctx, user).await?;
+    Ok(Json(response))
+}
+
+pub async fn edit_me(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    ValidatedJson(body): ValidatedJson<EditUserBody>,
+) -> HttpResult<Json<MyUser>> {
+    let response = user::edit_me(&ctx, user, body).await?;
+    Ok(Json(response))
+}
+
+pub async fn set_avatar(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    multipart: Multipart,
+) -> HttpResult<Json<MyUser>> {
+    let response = user::set_avatar(&ctx, user, multipart).await?;
+    Ok(Json(response))
+}
+
+pub async fn remove_avatar(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+) -> HttpResult<Json<MyUser>> {
+    let response = user::remove_avatar(&ctx, user).await?;
+    Ok(Json(response))
+}
+
+pub async fn get_user(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    viewer: MaybeAuthUser,
+    Path(username): Path<String>,
+) -> HttpResult<Json<UserProfile>> {
+    let response = user::get_by_username(&ctx, viewer, username).await?;
+    Ok(Json(response))
+}
+
+pub async fn follow_user(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path(username): Path<String>,
+) -> HttpResult<()> {
+    follow::follow(&ctx, user, username).await?;
+    Ok(())
+}
+
+pub async fn unfollow_user(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path(username): Path<String>,
+) -> HttpResult<()> {
+    follow::unfollow(&ctx, user, username).await?;
+    Ok(())
+}
+
+pub async fn get_followers(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    Path(username): Path<String>,
+    Query(query): Query<FollowsQuery>,
+) -> HttpResult<Json<Vec<User>>> {
+    let response = follow::followers(&ctx, username, query).await?;
+    Ok(Json(response))
+}
+
+pub async fn get_following(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    Path(username): Path<String>,
+    Query(query): Query<FollowsQuery>,
+) -> HttpResult<Json<Vec<User>>> {
+    let response = follow::following(&ctx, username, query).await?;
+    Ok(Json(response))
+}
+
+pub async fn get_mutuals(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    Path(username): Path<String>,
+    Query(query): Query<FollowsQuery>,
+) -> HttpResult<Json<Vec<User>>> {
+    let response = follow::mutuals(&ctx, username, query).await?;
+    Ok(Json(response))
+}
+
+pub async fn block_user(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path(username): Path<String>,
+) -> HttpResult<()> {
+    block::block(&ctx, user, username).await?;
+    Ok(())
+}
+
+pub async fn unblock_user(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+    Path(username): Path<String>,
+) -> HttpResult<()> {
+    block::unblock(&ctx, user, username).await?;
+    Ok(())
+}
+
+pub async fn get_blocked(
+    Extension(ctx): Extension<Arc<HttpContext>>,
+    user: AuthUser,
+) -> HttpResult<Json<Vec<User>>> {
+    let response = block::list(&ctx, user).await?;
+    Ok(Json(response))
+}
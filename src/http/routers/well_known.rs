@@ -0,0 +1,18 @@
+use axum::{Router, routing::get, Json, http::header, response::IntoResponse};
+use crate::utils::keys;
+
+pub fn router() -> Router {
+    Router::new()
+        .route(
+            "/jwks.json",
+            get(get_jwks)
+        )
+}
+
+/// Public keys for verifying our tokens by other services
+async fn get_jwks() -> impl IntoResponse {
+    (
+        [(header::CACHE_CONTROL, "public, max-age=300")],
+        Json(keys::jwks())
+    )
+}
@@ -15,6 +15,8 @@ pub async fn run() {
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     let listener = TcpListener::bind(&addr).await.unwrap();
     
+    utils::keys::spawn_reloader();
+
     log::info!("Starting server on http://{addr}");
     axum::serve(
         listener,
@@ -22,4 +24,31 @@ pub async fn run() {
             .await
             .into_make_service_with_connect_info::<SocketAddr>()
     ).await.expect("failed to start the server");
+}
+
+/// Generates a new signing key, previous ones are only used for verification
+pub fn rotate_keys() {
+    match utils::keys::rotate() {
+        Ok(kid) => log::info!("Rotated keys, new key id: {kid}"),
+        Err(e) => {
+            log::error!("failed to rotate keys: {e:?}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Gives the role to the user, e.g. to create the first admin
+pub async fn grant_role(username: &str, role: &str) {
+    let postgres_url = std::env::var("DATABASE_URL")
+        .expect("DATABASE_URL env variable is not set");
+    let pool = sqlx::PgPool::connect(&postgres_url).await
+        .expect("failed to connect to the database");
+
+    match logic::role::assign_by_username(&pool, &username.to_lowercase(), role).await {
+        Ok(()) => log::info!("Role {role} was given to {username}, it is applied after token refresh"),
+        Err(e) => {
+            log::error!("failed to give role: {e:?}");
+            std::process::exit(1);
+        }
+    }
 }
\ No newline at end of file
@@ -2,4 +2,22 @@
 //! Main application logic.
 
 pub mod health;
-pub mod auth;
\ No newline at end of file
+pub mod auth;
+pub mod session;
+pub mod verification;
+pub mod password_reset;
+pub mod two_factor;
+pub mod oidc;
+pub mod lockout;
+pub mod role;
+pub mod admin;
+pub mod audit;
+pub mod user;
+pub mod follow;
+pub mod block;
+pub mod chat;
+pub mod message;
+pub mod upload;
+pub mod event;
+pub mod gateway;
+pub mod presence;
\ No newline at end of file
@@ -0,0 +1,365 @@
+//! Managing users by admins.
+//! Every action is recorded by [audit], changes are recorded in the same transaction.
+//! Admins can not suspend or delete themselves, so there is always someone who can undo it.
+
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::{
+    http::{AuthUser, HttpError, HttpErrorContext, HttpResult, HttpContext, ResultExt},
+    logic::{audit::{self, Action}, password_reset, session},
+    models::{
+        database_models::{ManagedUser, Session},
+        http_models::{SuspendBody, UserSearchQuery},
+    },
+};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 100;
+
+/// Fails if the user is suspended, the message contains the reason.
+/// Checked when user logs in and when access tokens are checked.
+pub async fn check_suspension(pool: &PgPool, user_id: Uuid) -> HttpResult<()> {
+    let suspension = sqlx::query!(
+        r#"
+        SELECT "reason", "suspended_until"
+        FROM "user_suspension"
+        WHERE "user_id" = $1
+        AND ("suspended_until" IS NULL OR "suspended_until" > NOW())
+        "#,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let Some(suspension) = suspension else {
+        return Ok(());
+    };
+    let message = match suspension.suspended_until {
+        Some(until) => format!(
+            "Account is suspended until {}: {}",
+            until.format(&Rfc3339).unwrap_or_default(),
+            suspension.reason
+        ),
+        None => format!("Account is suspended: {}", suspension.reason),
+    };
+    Err(HttpError::bad_request(message))
+}
+
+async fn fetch_user(executor: impl PgExecutor<'_>, user_id: Uuid) -> HttpResult<ManagedUser> {
+    let user = sqlx::query_as!(
+        ManagedUser,
+        r#"
+        SELECT
+            u."id", u."username", u."email", u."display_name", u."email_verified_at", u."created_at",
+            ARRAY(
+                SELECT "role" FROM "user_role"
+                WHERE "user_id" = u."id"
+                ORDER BY "role"
+            ) AS "roles!",
+            (s."user_id" IS NOT NULL AND (s."suspended_until" IS NULL OR s."suspended_until" > NOW())) AS "suspended!",
+            s."suspended_until" AS "suspended_until?",
+            s."reason" AS "suspension_reason?"
+        FROM "user" u
+        LEFT JOIN "user_suspension" s ON s."user_id" = u."id"
+        WHERE u."id" = $1
+        "#,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await?
+    .http_context(HttpError::not_found("User not found"))?;
+
+    Ok(user)
+}
+
+/// Deletes all sessions of the user, their access tokens have to be revoked after commit
+async fn delete_sessions(tx: &mut Transaction<'_, Postgres>, user_id: Uuid) -> HttpResult<Vec<Uuid>> {
+    let sessions = sqlx::query!(
+        r#"
+        DELETE FROM "user_session"
+        WHERE "user_id" = $1
+        RETURNING "id"
+        "#,
+        user_id
+    )
+    .fetch_all(&mut **tx)
+    .await?
+    .into_iter()
+    .map(|session| session.id)
+    .collect();
+
+    Ok(sessions)
+}
+
+fn not_yourself(admin: &AuthUser, user_id: Uuid) -> HttpResult<()> {
+    if admin.user_id == user_id {
+        return Err(HttpError::bad_request("You can not do it to yourself"));
+    }
+    Ok(())
+}
+
+/// Searches by part of username, e-mail or display name, returns everyone without a query
+pub async fn search(
+    ctx: &HttpContext,
+    admin: AuthUser,
+    query: UserSearchQuery,
+) -> HttpResult<Vec<ManagedUser>> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let pattern = query.query.as_deref().map(|query| {
+        let escaped = query
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        format!("%{escaped}%")
+    });
+
+    // ILIKE does not support case insensitive collation of username and e-mail
+    let users = sqlx::query_as!(
+        ManagedUser,
+        r#"
+        SELECT
+            u."id", u."username", u."email", u."display_name", u."email_verified_at", u."created_at",
+            ARRAY(
+                SELECT "role" FROM "user_role"
+                WHERE "user_id" = u."id"
+                ORDER BY "role"
+            ) AS "roles!",
+            (s."user_id" IS NOT NULL AND (s."suspended_until" IS NULL OR s."suspended_until" > NOW())) AS "suspended!",
+            s."suspended_until" AS "suspended_until?",
+            s."reason" AS "suspension_reason?"
+        FROM "user" u
+        LEFT JOIN "user_suspension" s ON s."user_id" = u."id"
+        WHERE (
+            $1::TEXT IS NULL
+            OR u."username" COLLATE "default" ILIKE $1
+            OR u."email" COLLATE "default" ILIKE $1
+            OR u."display_name" ILIKE $1
+        )
+        AND (
+            $2::UUID IS NULL
+            OR (u."created_at", u."id") < (
+                SELECT "created_at", "id" FROM "user"
+                WHERE "id" = $2
+            )
+        )
+        ORDER BY u."created_at" DESC, u."id" DESC
+        LIMIT $3
+        "#,
+        pattern,
+        query.before,
+        limit
+    )
+    .fetch_all(&ctx.pool)
+    .await?;
+
+    audit::record(
+        &ctx.pool,
+        admin.user_id,
+        Action::SearchUsers,
+        None,
+        query.query.unwrap_or_default(),
+    )
+    .await?;
+
+    Ok(users)
+}
+
+pub async fn get(ctx: &HttpContext, admin: AuthUser, user_id: Uuid) -> HttpResult<ManagedUser> {
+    let user = fetch_user(&ctx.pool, user_id).await?;
+    audit::record(&ctx.pool, admin.user_id, Action::ViewUser, Some(user_id), "").await?;
+    Ok(user)
+}
+
+pub async fn sessions(ctx: &HttpContext, admin: AuthUser, user_id: Uuid) -> HttpResult<Vec<Session>> {
+    let sessions = sqlx::query_as!(
+        Session,
+        r#"
+        SELECT
+            "family_id" AS "id", "user_ip", "user_agent", "user_country", "user_city", "last_active",
+            FALSE AS "current!"
+        FROM "user_session"
+        WHERE "user_id" = $1
+        ORDER BY "last_active" DESC
+        "#,
+        user_id
+    )
+    .fetch_all(&ctx.pool)
+    .await?;
+
+    audit::record(&ctx.pool, admin.user_id, Action::ViewSessions, Some(user_id), "").await?;
+    Ok(sessions)
+}
+
+/// Logs the user out from every device
+pub async fn logout(ctx: &HttpContext, admin: AuthUser, user_id: Uuid) -> HttpResult<()> {
+    let mut tx = ctx.pool.begin().await?;
+
+    let revoked = delete_sessions(&mut tx, user_id).await?;
+    audit::record(
+        &mut *tx,
+        admin.user_id,
+        Action::Logout,
+        Some(user_id),
+        format!("sessions: {}", revoked.len()),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    session::revoke_tokens(ctx, &revoked).await;
+    Ok(())
+}
+
+/// Suspends the user or changes the existing suspension.
+/// User is logged out everywhere and can not log in until it ends.
+pub async fn suspend(
+    ctx: &HttpContext,
+    admin: AuthUser,
+    user_id: Uuid,
+    body: SuspendBody,
+) -> HttpResult<ManagedUser> {
+    not_yourself(&admin, user_id)?;
+    let until = body.until.map(|until| until.0);
+    if until.is_some_and(|until| until <= OffsetDateTime::now_utc()) {
+        return Err(HttpError::bad_request("Suspension must end in the future"));
+    }
+
+    let mut tx = ctx.pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO "user_suspension" ("user_id", "reason", "suspended_until", "suspended_by")
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT ("user_id") DO UPDATE
+        SET
+            "reason" = EXCLUDED."reason",
+            "suspended_until" = EXCLUDED."suspended_until",
+            "suspended_by" = EXCLUDED."suspended_by",
+            "created_at" = NOW()
+        "#,
+        user_id,
+        body.reason,
+        until,
+        admin.user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .on_constraint("user_suspension_user_id_fkey", |_| {
+        HttpError::not_found("User not found")
+    })?;
+
+    let revoked = delete_sessions(&mut tx, user_id).await?;
+
+    let until = match until {
+        Some(until) => until.format(&Rfc3339).unwrap_or_default(),
+        None => "forever".to_string(),
+    };
+    audit::record(
+        &mut *tx,
+        admin.user_id,
+        Action::Suspend,
+        Some(user_id),
+        format!("until: {until}, reason: {}", body.reason),
+    )
+    .await?;
+
+    let user = fetch_user(&mut *tx, user_id).await?;
+    tx.commit().await?;
+
+    session::revoke_tokens(ctx, &revoked).await;
+    Ok(user)
+}
+
+pub async fn unsuspend(ctx: &HttpContext, admin: AuthUser, user_id: Uuid) -> HttpResult<ManagedUser> {
+    let mut tx = ctx.pool.begin().await?;
+
+    let removed = sqlx::query!(
+        r#"
+        DELETE FROM "user_suspension"
+        WHERE "user_id" = $1
+        "#,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    if removed == 0 {
+        return Err(HttpError::not_found("User is not suspended"));
+    }
+    audit::record(&mut *tx, admin.user_id, Action::Unsuspend, Some(user_id), "").await?;
+
+    let user = fetch_user(&mut *tx, user_id).await?;
+    tx.commit().await?;
+
+    Ok(user)
+}
+
+/// Removes the current password, logs the user out everywhere
+/// and sends a link to set a new one, e.g. when the account was taken over.
+/// Providers linked to the account still work.
+pub async fn reset_password(ctx: &HttpContext, admin: AuthUser, user_id: Uuid) -> HttpResult<()> {
+    let mut tx = ctx.pool.begin().await?;
+
+    let email = sqlx::query!(
+        r#"
+        UPDATE "user"
+        SET "password_hash" = NULL
+        WHERE "id" = $1
+        RETURNING "email"
+        "#,
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .http_context(HttpError::not_found("User not found"))?
+    .email;
+
+    let revoked = delete_sessions(&mut tx, user_id).await?;
+    let token = password_reset::create_token(&mut tx, user_id).await?;
+    audit::record(&mut *tx, admin.user_id, Action::ResetPassword, Some(user_id), "").await?;
+
+    tx.commit().await?;
+
+    session::revoke_tokens(ctx, &revoked).await;
+    tokio::spawn(password_reset::send(email, token));
+    Ok(())
+}
+
+/// Deletes the user with everything that belongs to them, it can not be undone
+pub async fn delete(ctx: &HttpContext, admin: AuthUser, user_id: Uuid) -> HttpResult<()> {
+    not_yourself(&admin, user_id)?;
+
+    let mut tx = ctx.pool.begin().await?;
+
+    let revoked = delete_sessions(&mut tx, user_id).await?;
+    let user = sqlx::query!(
+        r#"
+        DELETE FROM "user"
+        WHERE "id" = $1
+        RETURNING "username", "email"
+        "#,
+        user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .http_context(HttpError::not_found("User not found"))?;
+
+    // the user is gone, so the log is the only place left with who it was
+    audit::record(
+        &mut *tx,
+        admin.user_id,
+        Action::DeleteUser,
+        Some(user_id),
+        format!("username: {}, email: {}", user.username, user.email),
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    log::info!("user {} ({user_id}) deleted by {}", user.username, admin.user_id);
+    session::revoke_tokens(ctx, &revoked).await;
+    Ok(())
+}
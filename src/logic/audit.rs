@@ -0,0 +1,98 @@
+//! Log of actions admins take on users.
+//! Actions are recorded in the same transaction as the change they describe.
+
+use sqlx::PgExecutor;
+use uuid::Uuid;
+
+use crate::{
+    http::{HttpResult, HttpContext},
+    models::{database_models::AuditEntry, http_models::AuditQuery},
+};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 100;
+
+#[derive(Clone, Copy)]
+pub enum Action {
+    SearchUsers,
+    ViewUser,
+    ViewSessions,
+    Logout,
+    Suspend,
+    Unsuspend,
+    ResetPassword,
+    DeleteUser,
+    AssignRole,
+    RemoveRole,
+}
+
+impl Action {
+    /// Value of the `action` column
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::SearchUsers => "search_users",
+            Self::ViewUser => "view_user",
+            Self::ViewSessions => "view_sessions",
+            Self::Logout => "logout",
+            Self::Suspend => "suspend",
+            Self::Unsuspend => "unsuspend",
+            Self::ResetPassword => "reset_password",
+            Self::DeleteUser => "delete_user",
+            Self::AssignRole => "assign_role",
+            Self::RemoveRole => "remove_role",
+        }
+    }
+}
+
+/// Pass the transaction of the change, or the pool for actions which do not change anything
+pub async fn record(
+    executor: impl PgExecutor<'_>,
+    admin_id: Uuid,
+    action: Action,
+    target_user_id: Option<Uuid>,
+    details: impl Into<String>,
+) -> HttpResult<()> {
+    sqlx::query!(
+        r#"
+        INSERT INTO "admin_audit_log" ("admin_id", "action", "target_user_id", "details")
+        VALUES ($1, $2, $3, $4)
+        "#,
+        admin_id,
+        action.as_str(),
+        target_user_id,
+        details.into()
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn list(ctx: &HttpContext, query: AuditQuery) -> HttpResult<Vec<AuditEntry>> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let entries = sqlx::query_as!(
+        AuditEntry,
+        r#"
+        SELECT "id", "admin_id", "action", "target_user_id", "details", "created_at"
+        FROM "admin_audit_log"
+        WHERE ($1::UUID IS NULL OR "target_user_id" = $1)
+        AND (
+            $2::UUID IS NULL
+            OR ("created_at", "id") < (
+                SELECT "created_at", "id" FROM "admin_audit_log"
+                WHERE "id" = $2
+            )
+        )
+        ORDER BY "created_at" DESC, "id" DESC
+        LIMIT $3
+        "#,
+        query.user_id,
+        query.before,
+        limit
+    )
+    .fetch_all(&ctx.pool)
+    .await?;
+
+    Ok(entries)
+}
@@ -1,18 +1,26 @@
 use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
 
 use crate::{
-    http::{AuthUser, RequestInfo, HttpError, HttpResult, HttpContext},
+    http::{AuthUser, RequestInfo, HttpError, HttpErrorContext, HttpResult, HttpContext},
+    logic::{admin, lockout, role, session, two_factor, verification},
     models::{
+        TimestamptzOption,
         database_models::User,
-        http_models::{AuthResponse, LoginBody, RefreshBody, RegisterBody},
+        http_models::{AuthResponse, LoginBody, LoginMfaBody, LoginResponse, RefreshBody, RegisterBody},
     },
     utils::{
-        password::{hash_password, verify_password},
-        tokens::{Claims, TokenPair},
+        password::{hash_password, needs_rehash, verify_password},
+        password_policy,
+        tokens::{Claims, MfaClaims, TokenPair, REFRESH_LIFE_TIME},
     },
 };
 
-async fn username_exists(pool: &PgPool, username: &str) -> HttpResult<bool> {
+/// Users without a password must have logged in this recently to do sensitive changes
+const FRESH_LOGIN: Duration = Duration::minutes(10);
+
+pub async fn username_exists(pool: &PgPool, username: &str) -> HttpResult<bool> {
     let exists = sqlx::query!(
         r#"
         SELECT COUNT(1) FROM "user"
@@ -27,7 +35,7 @@ async fn username_exists(pool: &PgPool, username: &str) -> HttpResult<bool> {
     Ok(exists)
 }
 
-async fn email_exists(pool: &PgPool, email: &str) -> HttpResult<bool> {
+pub async fn email_exists(pool: &PgPool, email: &str) -> HttpResult<bool> {
     let exists = sqlx::query!(
         r#"
         SELECT COUNT(1) FROM "user"
@@ -58,6 +66,7 @@ pub async fn register(
         // Email taken error
         return Err(HttpError::bad_request("Email is already taken"));
     }
+    password_policy::check(&body.password, &username, &email).await?;
     let password_hash = hash_password(body.password).await?;
 
     let user = sqlx::query_as!(
@@ -69,77 +78,164 @@ pub async fn register(
             "password_hash",
             "display_name"
         ) VALUES ($1, $2, $3, $1)
-        RETURNING "id", "username", "display_name", "avatar", "status", NULL::TIMESTAMPTZ AS "online"
+        RETURNING "id", "username", "display_name", "avatar", "status",
+            FALSE AS "online!", NULL::TIMESTAMPTZ AS "last_seen", "hide_last_seen"
         "#, username, email, password_hash
     )
     .fetch_one(&ctx.pool)
     .await?;
 
-    let tokens = TokenPair::new(user.id).await?;
+    verification::send(user.id, email);
 
-    let info = info.fetch_location(&ctx.client).await?;
+    create_session(ctx, user, info).await
+}
 
-    sqlx::query!(
+pub async fn login(
+    ctx: &HttpContext,
+    body: LoginBody,
+    info: RequestInfo,
+) -> HttpResult<LoginResponse> {
+    let login = body.login.to_lowercase();
+    let lockout_key = format!("login:{login}:{}", info.ip);
+    lockout::check(ctx, &lockout_key).await?;
+
+    // both columns use case insensitive collation
+    let row = sqlx::query!(
         r#"
-        INSERT INTO user_session (
-            "id",
-            "user_id",
-            "user_ip",
-            "user_agent",
-            "user_country",
-            "user_city"
-        ) VALUES ($1, $2, $3, $4, $5, $6)
+        SELECT "id", "username", "display_name", "avatar", "status", "hide_last_seen", "password_hash"
+        FROM "user"
+        WHERE "username" = $1
+        OR "email" = $1
         "#,
-        tokens.id,
-        user.id,
-        info.ip,
-        info.agent,
-        info.country,
-        info.city
+        login
     )
-    .execute(&ctx.pool)
+    .fetch_optional(&ctx.pool)
     .await?;
 
-    Ok(AuthResponse { user, tokens })
+    // Login or password is wrong
+    let wrong = HttpError::bad_request("Login or password is wrong");
+    let Some(row) = row else {
+        lockout::fail(ctx, &lockout_key).await?;
+        return Err(wrong);
+    };
+    // users registered with identity providers may have no password
+    let Some(password_hash) = row.password_hash else {
+        lockout::fail(ctx, &lockout_key).await?;
+        return Err(wrong);
+    };
+    if verify_password(body.password.clone(), password_hash.clone()).await.is_err() {
+        lockout::fail(ctx, &lockout_key).await?;
+        return Err(wrong);
+    }
+    lockout::reset(ctx, &lockout_key).await?;
+
+    if needs_rehash(&password_hash) {
+        tokio::spawn(rehash_password(ctx.pool.clone(), row.id, body.password, password_hash));
+    }
+
+    let user = User {
+        id: row.id,
+        username: row.username,
+        display_name: row.display_name,
+        avatar: row.avatar,
+        status: row.status,
+        online: false,
+        last_seen: TimestamptzOption(None),
+        hide_last_seen: row.hide_last_seen,
+    };
+
+    finish_login(ctx, user, info).await
 }
 
-pub async fn login(
+/// Creates a session, or gives a ticket for the second step
+/// if user has two-factor authentication
+pub async fn finish_login(
     ctx: &HttpContext,
-    body: LoginBody,
+    user: User,
+    info: RequestInfo,
+) -> HttpResult<LoginResponse> {
+    admin::check_suspension(&ctx.pool, user.id).await?;
+
+    if two_factor::is_enabled(&ctx.pool, user.id).await? {
+        return Ok(LoginResponse::MfaRequired {
+            mfa_ticket: MfaClaims::encode(user.id)?,
+        });
+    }
+
+    let response = create_session(ctx, user, info).await?;
+    Ok(LoginResponse::Authenticated(response))
+}
+
+/// Upgrades the hash to current Argon2 parameters and pepper,
+/// it is only possible while the password is known.
+/// Skipped if the password was changed or reset in the meantime.
+async fn rehash_password(pool: PgPool, user_id: Uuid, password: String, old_hash: String) {
+    let result: HttpResult<()> = async {
+        let password_hash = hash_password(password).await?;
+        sqlx::query!(
+            r#"
+            UPDATE "user"
+            SET "password_hash" = $2
+            WHERE "id" = $1
+            AND "password_hash" = $3
+            "#,
+            user_id,
+            password_hash,
+            old_hash
+        )
+        .execute(&pool)
+        .await?;
+        Ok(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        log::error!("failed to rehash password: {e:?}");
+    }
+}
+
+/// Second step of the login for users with two-factor authentication
+pub async fn login_mfa(
+    ctx: &HttpContext,
+    body: LoginMfaBody,
     info: RequestInfo,
 ) -> HttpResult<AuthResponse> {
-    let username = body.username.to_lowercase();
-    let password_hash = sqlx::query!(
-        r#"
-        SELECT "password_hash" FROM "user"
-        WHERE username = $1
-        "#,
-        username
-    )
-    .fetch_optional(&ctx.pool)
-    .await?
-    // Username or password is wrong
-    .ok_or(HttpError::bad_request("Username or password is wrong"))?
-    .password_hash;
+    let claims = MfaClaims::parse(&body.ticket)
+        .map_err(|_| HttpError::bad_request("Login ticket is invalid or expired"))?;
+
+    two_factor::check_code(ctx, claims.user_id, &body.code).await?;
 
-    verify_password(body.password, password_hash)
-        .await
-        // Username or password is wrong
-        .map_err(|_| HttpError::bad_request("Username or password is wrong"))?;
+    // user could be suspended after receiving the ticket
+    admin::check_suspension(&ctx.pool, claims.user_id).await?;
+
+    create_session(ctx, get_user(&ctx.pool, claims.user_id).await?, info).await
+}
 
+pub async fn get_user(pool: &PgPool, user_id: Uuid) -> HttpResult<User> {
     let user = sqlx::query_as!(
         User,
         r#"
-        SELECT "id", "username", "display_name", "avatar", "status", NULL::TIMESTAMPTZ AS "online"
+        SELECT "id", "username", "display_name", "avatar", "status",
+            FALSE AS "online!", NULL::TIMESTAMPTZ AS "last_seen", "hide_last_seen"
         FROM "user"
-        WHERE username = $1
+        WHERE "id" = $1
         "#,
-        body.username
+        user_id
     )
-    .fetch_one(&ctx.pool)
+    .fetch_one(pool)
     .await?;
 
-    let tokens = TokenPair::new(user.id).await?;
+    Ok(user)
+}
+
+/// Creates a new session with a new token pair
+async fn create_session(
+    ctx: &HttpContext,
+    user: User,
+    info: RequestInfo,
+) -> HttpResult<AuthResponse> {
+    let grants = role::grants(&ctx.pool, user.id).await?;
+    let tokens = TokenPair::new(user.id, grants).await?;
 
     let info = info.fetch_location(&ctx.client).await?;
 
@@ -147,12 +243,13 @@ pub async fn login(
         r#"
         INSERT INTO user_session (
             "id",
+            "family_id",
             "user_id",
             "user_ip",
             "user_agent",
             "user_country",
             "user_city"
-        ) VALUES ($1, $2, $3, $4, $5, $6)
+        ) VALUES ($1, $1, $2, $3, $4, $5, $6)
         "#,
         tokens.id,
         user.id,
@@ -167,42 +264,136 @@ pub async fn login(
     Ok(AuthResponse { user, tokens })
 }
 
+/// Every refresh token can only be used once.
+/// Ids of used tokens are remembered, and if one of them is used again,
+/// someone else has a copy of it, so the whole session is revoked.
 pub async fn refresh(
     ctx: &HttpContext,
     body: RefreshBody,
     info: RequestInfo,
 ) -> HttpResult<TokenPair> {
-    let claims = Claims::parse(&body.refresh_token)?;
+    let claims = Claims::parse_refresh(&body.refresh_token)
+        .map_err(|_| HttpError::Unauthorized)?;
 
-    let tokens = TokenPair::new(claims.user_id).await?;
+    // roles could change since the previous pair
+    let grants = role::grants(&ctx.pool, claims.user_id).await?;
+    let tokens = TokenPair::new(claims.user_id, grants).await?;
 
     let info = info.fetch_location(&ctx.client).await?;
 
-    sqlx::query!(
+    let mut tx = ctx.pool.begin().await?;
+
+    let session = sqlx::query!(
         r#"
         UPDATE user_session
         SET
-            "id" = $2,
-            "user_agent" = $3,
-            "user_ip" = $4,
-            "user_country" = $5,
-            "user_city" = $6,
+            "id" = $3,
+            "user_agent" = $4,
+            "user_ip" = $5,
+            "user_country" = $6,
+            "user_city" = $7,
             "last_active" = NOW()
         WHERE "id" = $1
+        AND "user_id" = $2
+        RETURNING "family_id"
         "#,
         claims.jti,
+        claims.user_id,
         tokens.id,
         info.agent,
         info.ip,
         info.country,
         info.city
     )
-    .execute(&ctx.pool)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(session) = session else {
+        tx.rollback().await?;
+        revoke_reused(ctx, &claims, &info.ip).await?;
+        return Err(HttpError::Unauthorized);
+    };
+
+    sqlx::query!(
+        r#"
+        INSERT INTO "user_session_rotated" ("id", "family_id", "user_id")
+        VALUES ($1, $2, $3)
+        "#,
+        claims.jti,
+        session.family_id,
+        claims.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // expired tokens can not be reused anyway
+    sqlx::query!(
+        r#"
+        DELETE FROM "user_session_rotated"
+        WHERE "user_id" = $1
+        AND "rotated_at" < $2
+        "#,
+        claims.user_id,
+        OffsetDateTime::now_utc() - REFRESH_LIFE_TIME
+    )
+    .execute(&mut *tx)
     .await?;
 
+    tx.commit().await?;
+
+    // access token of the previous pair stops working too
+    session::revoke_tokens(ctx, &[claims.jti]).await;
+
     Ok(tokens)
 }
 
+/// Called when refresh token does not belong to any session.
+/// If it was already used, the session it belonged to is revoked.
+async fn revoke_reused(ctx: &HttpContext, claims: &Claims, ip: &str) -> HttpResult<()> {
+    let rotated = sqlx::query!(
+        r#"
+        SELECT "family_id" FROM "user_session_rotated"
+        WHERE "id" = $1
+        AND "user_id" = $2
+        "#,
+        claims.jti,
+        claims.user_id
+    )
+    .fetch_optional(&ctx.pool)
+    .await?;
+
+    let Some(rotated) = rotated else {
+        // session was deleted, e.g. user logged out
+        return Ok(());
+    };
+
+    let revoked: Vec<Uuid> = sqlx::query!(
+        r#"
+        DELETE FROM "user_session"
+        WHERE "family_id" = $1
+        RETURNING "id"
+        "#,
+        rotated.family_id
+    )
+    .fetch_all(&ctx.pool)
+    .await?
+    .into_iter()
+    .map(|session| session.id)
+    .collect();
+    session::revoke_tokens(ctx, &revoked).await;
+
+    log::warn!(
+        "SECURITY: reused refresh token\nuser_id: {}\ntoken id: {}\nsession family: {}\nip: {}\nrevoked sessions: {}",
+        claims.user_id,
+        claims.jti,
+        rotated.family_id,
+        ip,
+        revoked.len()
+    );
+
+    Ok(())
+}
+
 pub async fn logout(ctx: &HttpContext, user: AuthUser) -> HttpResult<()> {
     sqlx::query!(
         r#"
@@ -214,5 +405,47 @@ pub async fn logout(ctx: &HttpContext, user: AuthUser) -> HttpResult<()> {
     .execute(&ctx.pool)
     .await?;
 
+    session::revoke_tokens(ctx, &[user.session_id]).await;
+
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Someone with a stolen access token must not be able to take over the account,
+/// so sensitive changes check the password, or need a recent login for users without one.
+/// `action` is shown in the error, e.g. "change the password".
+pub async fn reauthenticate(
+    ctx: &HttpContext,
+    user: &AuthUser,
+    password: Option<String>,
+    action: &str,
+) -> HttpResult<()> {
+    let row = sqlx::query!(
+        r#"
+        SELECT u."password_hash", s."created_at" AS "logged_in_at"
+        FROM "user" u
+        JOIN "user_session" s ON s."user_id" = u."id"
+        WHERE u."id" = $1
+        AND s."id" = $2
+        "#,
+        user.user_id,
+        user.session_id
+    )
+    .fetch_one(&ctx.pool)
+    .await?;
+
+    let Some(password_hash) = row.password_hash else {
+        if row.logged_in_at < OffsetDateTime::now_utc() - FRESH_LOGIN {
+            return Err(HttpError::bad_request(format!("Log in again to {action}")));
+        }
+        return Ok(());
+    };
+    let password = password.http_context(HttpError::bad_request("Current password is required"))?;
+
+    let lockout_key = format!("password:{}", user.user_id);
+    lockout::check(ctx, &lockout_key).await?;
+    if verify_password(password, password_hash).await.is_err() {
+        lockout::fail(ctx, &lockout_key).await?;
+        return Err(HttpError::bad_request("Password is wrong"));
+    }
+    lockout::reset(ctx, &lockout_key).await
+}
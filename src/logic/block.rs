@@ -0,0 +1,105 @@
+//! Blocking itself is enforced by the database triggers,
+//! blocked user gets [HttpError::Forbidden] when trying to follow the blocker,
+//! open a private chat or send a message to them.
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    http::{AuthUser, HttpError, HttpResult, HttpContext, ResultExt},
+    logic::{presence, user::get_id},
+    models::database_models::User,
+};
+
+/// Checks if `blocker` has blocked `blocked`
+pub async fn is_blocked(pool: &PgPool, blocker: Uuid, blocked: Uuid) -> HttpResult<bool> {
+    let blocked = sqlx::query!(
+        r#"
+        SELECT is_blocked($1, $2) AS "blocked!"
+        "#,
+        blocker,
+        blocked
+    )
+    .fetch_one(pool)
+    .await?
+    .blocked;
+
+    Ok(blocked)
+}
+
+/// Blocking also removes subscriptions between users
+pub async fn block(ctx: &HttpContext, user: AuthUser, username: String) -> HttpResult<()> {
+    let to_user_id = get_id(&ctx.pool, &username).await?;
+
+    let mut tx = ctx.pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO "user_block_user" ("from_user_id", "to_user_id")
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        user.user_id,
+        to_user_id
+    )
+    .execute(&mut *tx)
+    .await
+    .on_constraint("user_block_user_check", |_| {
+        HttpError::bad_request("You can not block yourself")
+    })?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM "user_subscribe_user"
+        WHERE ("from_user_id" = $1 AND "to_user_id" = $2)
+        OR ("from_user_id" = $2 AND "to_user_id" = $1)
+        "#,
+        user.user_id,
+        to_user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub async fn unblock(ctx: &HttpContext, user: AuthUser, username: String) -> HttpResult<()> {
+    let to_user_id = get_id(&ctx.pool, &username).await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM "user_block_user"
+        WHERE "from_user_id" = $1
+        AND "to_user_id" = $2
+        "#,
+        user.user_id,
+        to_user_id
+    )
+    .execute(&ctx.pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Users blocked by the user, most recently blocked first
+pub async fn list(ctx: &HttpContext, user: AuthUser) -> HttpResult<Vec<User>> {
+    let mut users = sqlx::query_as!(
+        User,
+        r#"
+        SELECT u."id", u."username", u."display_name", u."avatar", u."status",
+            FALSE AS "online!", NULL::TIMESTAMPTZ AS "last_seen", u."hide_last_seen"
+        FROM "user_block_user" b
+        JOIN "user" u ON u."id" = b."to_user_id"
+        WHERE b."from_user_id" = $1
+        ORDER BY b."created_at" DESC
+        "#,
+        user.user_id
+    )
+    .fetch_all(&ctx.pool)
+    .await?;
+
+    presence::fill(ctx, &mut users).await?;
+    Ok(users)
+}
@@ -0,0 +1,335 @@
+use axum::extract::Multipart;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    http::{AuthUser, HttpError, HttpResult, HttpContext, ResultExt},
+    logic::upload,
+    models::{
+        database_models::{Chat, ChatListItem},
+        http_models::{CreateGroupChatBody, EditChatBody, OpenPrivateChatBody},
+    },
+};
+
+/// Returns [HttpError::Forbidden] if user is not a member of the chat.
+/// Non-existing chats are treated the same way, so their ids can not be guessed.
+pub async fn ensure_member(pool: &PgPool, chat_id: Uuid, user_id: Uuid) -> HttpResult<()> {
+    let is_member = sqlx::query!(
+        r#"
+        SELECT COUNT(1) FROM "chat_user"
+        WHERE "chat_id" = $1
+        AND "user_id" = $2
+        "#,
+        chat_id,
+        user_id
+    )
+    .fetch_one(pool)
+    .await?
+    .count
+        == Some(1);
+
+    if !is_member {
+        return Err(HttpError::Forbidden);
+    }
+    Ok(())
+}
+
+/// Serializes creation of chats that must be unique (private and saved ones),
+/// so two concurrent requests do not create two chats.
+/// The lock is released when the transaction ends.
+async fn lock(tx: &mut Transaction<'_, Postgres>, key: String) -> HttpResult<()> {
+    sqlx::query!(
+        r#"
+        SELECT 1 AS "locked" FROM pg_advisory_xact_lock(hashtextextended($1, 0))
+        "#,
+        key
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+async fn get_chat(pool: &PgPool, chat_id: Uuid) -> HttpResult<Chat> {
+    let chat = sqlx::query_as!(
+        Chat,
+        r#"
+        SELECT "id", "type" AS "chat_type", "name", "description", "image", "created_at"
+        FROM "chat"
+        WHERE "id" = $1
+        "#,
+        chat_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(chat)
+}
+
+pub async fn list(ctx: &HttpContext, user: AuthUser) -> HttpResult<Vec<ChatListItem>> {
+    let chats = sqlx::query_as!(
+        ChatListItem,
+        r#"
+        SELECT
+            c."id", c."type" AS "chat_type", c."name", c."description", c."image", c."created_at",
+            (
+                SELECT COUNT(1) FROM "message" m
+                WHERE m."chat_id" = c."id"
+                AND m."sender_id" != cu."user_id"
+                AND (
+                    cu."last_read_message_id" IS NULL
+                    OR (m."created_at", m."id") > (
+                        SELECT "created_at", "id" FROM "message"
+                        WHERE "id" = cu."last_read_message_id"
+                    )
+                )
+            ) AS "unread_count!"
+        FROM "chat" c
+        JOIN "chat_user" cu ON cu."chat_id" = c."id"
+        WHERE cu."user_id" = $1
+        ORDER BY c."created_at" DESC
+        "#,
+        user.user_id
+    )
+    .fetch_all(&ctx.pool)
+    .await?;
+
+    Ok(chats)
+}
+
+pub async fn get(ctx: &HttpContext, user: AuthUser, chat_id: Uuid) -> HttpResult<Chat> {
+    ensure_member(&ctx.pool, chat_id, user.user_id).await?;
+    get_chat(&ctx.pool, chat_id).await
+}
+
+pub async fn create_group(
+    ctx: &HttpContext,
+    user: AuthUser,
+    body: CreateGroupChatBody,
+) -> HttpResult<Chat> {
+    let mut members = body.members;
+    members.push(user.user_id);
+    members.sort();
+    members.dedup();
+
+    let mut tx = ctx.pool.begin().await?;
+
+    let chat = sqlx::query_as!(
+        Chat,
+        r#"
+        INSERT INTO "chat" ("type", "name", "description")
+        VALUES ('group', $1, $2)
+        RETURNING "id", "type" AS "chat_type", "name", "description", "image", "created_at"
+        "#,
+        body.name,
+        body.description
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO "chat_user" ("chat_id", "user_id")
+        SELECT $1, UNNEST($2::UUID[])
+        "#,
+        chat.id,
+        &members
+    )
+    .execute(&mut *tx)
+    .await
+    .on_constraint("chat_user_user_id_fkey", |_| {
+        HttpError::not_found("User not found")
+    })?;
+
+    tx.commit().await?;
+
+    Ok(chat)
+}
+
+/// Opens a private chat with another user.
+/// If it already exists, the existing one is returned.
+pub async fn open_private(
+    ctx: &HttpContext,
+    user: AuthUser,
+    body: OpenPrivateChatBody,
+) -> HttpResult<Chat> {
+    if body.user_id == user.user_id {
+        return Err(HttpError::bad_request("Use saved chat to message yourself"));
+    }
+
+    let (first, second) = if user.user_id < body.user_id {
+        (user.user_id, body.user_id)
+    } else {
+        (body.user_id, user.user_id)
+    };
+
+    let mut tx = ctx.pool.begin().await?;
+    lock(&mut tx, format!("private:{first}:{second}")).await?;
+
+    let existing = sqlx::query!(
+        r#"
+        SELECT c."id"
+        FROM "chat" c
+        JOIN "chat_user" a ON a."chat_id" = c."id" AND a."user_id" = $1
+        JOIN "chat_user" b ON b."chat_id" = c."id" AND b."user_id" = $2
+        WHERE c."type" = 'private'
+        "#,
+        first,
+        second
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let chat_id = match existing {
+        Some(chat) => chat.id,
+        None => {
+            let chat_id = sqlx::query!(
+                r#"
+                INSERT INTO "chat" ("type")
+                VALUES ('private')
+                RETURNING "id"
+                "#
+            )
+            .fetch_one(&mut *tx)
+            .await?
+            .id;
+
+            sqlx::query!(
+                r#"
+                INSERT INTO "chat_user" ("chat_id", "user_id")
+                VALUES ($1, $2), ($1, $3)
+                "#,
+                chat_id,
+                first,
+                second
+            )
+            .execute(&mut *tx)
+            .await
+            .on_constraint("chat_user_user_id_fkey", |_| {
+                HttpError::not_found("User not found")
+            })?;
+
+            chat_id
+        }
+    };
+
+    tx.commit().await?;
+
+    get_chat(&ctx.pool, chat_id).await
+}
+
+/// Returns user's "saved" chat, where they can send messages to themselves.
+/// It is created on first access.
+pub async fn get_saved(ctx: &HttpContext, user: AuthUser) -> HttpResult<Chat> {
+    let mut tx = ctx.pool.begin().await?;
+    lock(&mut tx, format!("saved:{}", user.user_id)).await?;
+
+    let existing = sqlx::query!(
+        r#"
+        SELECT c."id"
+        FROM "chat" c
+        JOIN "chat_user" cu ON cu."chat_id" = c."id"
+        WHERE c."type" = 'saved'
+        AND cu."user_id" = $1
+        "#,
+        user.user_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let chat_id = match existing {
+        Some(chat) => chat.id,
+        None => {
+            let chat_id = sqlx::query!(
+                r#"
+                INSERT INTO "chat" ("type")
+                VALUES ('saved')
+                RETURNING "id"
+                "#
+            )
+            .fetch_one(&mut *tx)
+            .await?
+            .id;
+
+            sqlx::query!(
+                r#"
+                INSERT INTO "chat_user" ("chat_id", "user_id")
+                VALUES ($1, $2)
+                "#,
+                chat_id,
+                user.user_id
+            )
+            .execute(&mut *tx)
+            .await?;
+
+            chat_id
+        }
+    };
+
+    tx.commit().await?;
+
+    get_chat(&ctx.pool, chat_id).await
+}
+
+/// Only group chats have details that can be edited.
+/// Image is changed with [set_image], so it is always an avatar made for the chat.
+pub async fn edit(
+    ctx: &HttpContext,
+    user: AuthUser,
+    chat_id: Uuid,
+    body: EditChatBody,
+) -> HttpResult<Chat> {
+    ensure_member(&ctx.pool, chat_id, user.user_id).await?;
+
+    let chat = sqlx::query_as!(
+        Chat,
+        r#"
+        UPDATE "chat"
+        SET
+            "name" = COALESCE($2, "name"),
+            "description" = COALESCE($3, "description")
+        WHERE "id" = $1
+        AND "type" = 'group'
+        RETURNING "id", "type" AS "chat_type", "name", "description", "image", "created_at"
+        "#,
+        chat_id,
+        body.name,
+        body.description
+    )
+    .fetch_optional(&ctx.pool)
+    .await?
+    .ok_or(HttpError::bad_request("Only group chats can be edited"))?;
+
+    Ok(chat)
+}
+
+pub async fn set_image(
+    ctx: &HttpContext,
+    user: AuthUser,
+    chat_id: Uuid,
+    multipart: Multipart,
+) -> HttpResult<Chat> {
+    ensure_member(&ctx.pool, chat_id, user.user_id).await?;
+
+    // check it before processing the image
+    if get_chat(&ctx.pool, chat_id).await?.chat_type != "group" {
+        return Err(HttpError::bad_request("Only group chats can be edited"));
+    }
+
+    let image = upload::create_avatar(ctx, multipart).await?;
+
+    let chat = sqlx::query_as!(
+        Chat,
+        r#"
+        UPDATE "chat"
+        SET "image" = $2
+        WHERE "id" = $1
+        RETURNING "id", "type" AS "chat_type", "name", "description", "image", "created_at"
+        "#,
+        chat_id,
+        image.id
+    )
+    .fetch_one(&ctx.pool)
+    .await?;
+
+    Ok(chat)
+}
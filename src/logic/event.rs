@@ -0,0 +1,159 @@
+//! Events are published to Redis, so every server instance receives them.
+//! Each instance listens to the channel and passes events to its WebSocket connections
+//! through a broadcast channel. Recipients are resolved once, when the event is published.
+
+use std::{sync::Arc, time::Duration};
+use futures_util::StreamExt;
+use serde::{Serialize, Deserialize};
+use sqlx::PgPool;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{
+    http::{HttpContext, HttpResult},
+    models::http_models::Event,
+};
+
+/// Redis channel all events go through
+const CHANNEL: &str = "events";
+/// How many events can wait for slow connections before they start missing them
+const CAPACITY: usize = 1024;
+
+/// What is actually sent to Redis
+#[derive(Serialize, Deserialize)]
+struct Envelope<E> {
+    recipients: Vec<Uuid>,
+    event: E,
+}
+
+/// Event received from Redis, already serialized for sending to users
+pub struct GatewayEvent {
+    pub recipients: Vec<Uuid>,
+    pub payload: String,
+}
+
+pub type EventSender = broadcast::Sender<Arc<GatewayEvent>>;
+
+/// Members of the chat
+pub async fn chat_members(pool: &PgPool, chat_id: Uuid) -> HttpResult<Vec<Uuid>> {
+    let members = sqlx::query!(
+        r#"
+        SELECT "user_id" FROM "chat_user"
+        WHERE "chat_id" = $1
+        "#,
+        chat_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| row.user_id)
+    .collect();
+
+    Ok(members)
+}
+
+/// Everyone who shares at least one chat with the user
+pub async fn contacts(pool: &PgPool, user_id: Uuid) -> HttpResult<Vec<Uuid>> {
+    let contacts = sqlx::query!(
+        r#"
+        SELECT DISTINCT cu."user_id"
+        FROM "chat_user" cu
+        JOIN "chat_user" my ON my."chat_id" = cu."chat_id"
+        WHERE my."user_id" = $1
+        AND cu."user_id" != $1
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|row| row.user_id)
+    .collect();
+
+    Ok(contacts)
+}
+
+async fn try_publish(ctx: &HttpContext, recipients: Vec<Uuid>, event: &Event) -> HttpResult<()> {
+    if recipients.is_empty() {
+        return Ok(());
+    }
+
+    let payload = serde_json::to_string(&Envelope { recipients, event })
+        .map_err(anyhow::Error::from)?;
+
+    redis::cmd("PUBLISH")
+        .arg(CHANNEL)
+        .arg(payload)
+        .query_async::<_, ()>(&mut ctx.redis.clone())
+        .await?;
+
+    Ok(())
+}
+
+/// Events are not critical, the action they describe has already happened.
+/// So failing to publish one is only logged.
+pub async fn publish(ctx: &HttpContext, recipients: Vec<Uuid>, event: Event) {
+    if let Err(e) = try_publish(ctx, recipients, &event).await {
+        log::error!("failed to publish event: {e:?}");
+    }
+}
+
+pub async fn publish_to_chat(ctx: &HttpContext, chat_id: Uuid, event: Event) {
+    match chat_members(&ctx.pool, chat_id).await {
+        Ok(members) => publish(ctx, members, event).await,
+        Err(e) => log::error!("failed to get chat members for event: {e:?}"),
+    }
+}
+
+pub async fn publish_to_contacts(ctx: &HttpContext, user_id: Uuid, event: Event) {
+    match contacts(&ctx.pool, user_id).await {
+        Ok(contacts) => publish(ctx, contacts, event).await,
+        Err(e) => log::error!("failed to get contacts for event: {e:?}"),
+    }
+}
+
+async fn listen(client: &redis::Client, sender: &EventSender) -> redis::RedisResult<()> {
+    let mut pubsub = client.get_async_connection().await?.into_pubsub();
+    pubsub.subscribe(CHANNEL).await?;
+    log::info!("Listening for events");
+
+    let mut messages = pubsub.on_message();
+    while let Some(message) = messages.next().await {
+        let payload: String = message.get_payload()?;
+        let envelope: Envelope<serde_json::Value> = match serde_json::from_str(&payload) {
+            Ok(envelope) => envelope,
+            Err(e) => {
+                log::error!("failed to parse event: {e}");
+                continue;
+            }
+        };
+
+        // It only fails when nobody is connected, which is fine
+        sender
+            .send(Arc::new(GatewayEvent {
+                recipients: envelope.recipients,
+                payload: envelope.event.to_string(),
+            }))
+            .ok();
+    }
+
+    Ok(())
+}
+
+/// Starts listening for events in the background.
+/// Connection to Redis is restored if it is lost.
+pub fn spawn_listener(client: redis::Client) -> EventSender {
+    let (sender, _) = broadcast::channel(CAPACITY);
+
+    let events = sender.clone();
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = listen(&client, &events).await {
+                log::error!("Events listener error: {e:?}");
+            }
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    });
+
+    sender
+}
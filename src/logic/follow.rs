@@ -0,0 +1,165 @@
+use crate::{
+    http::{AuthUser, HttpError, HttpResult, HttpContext, ResultExt},
+    logic::{presence, user::get_id},
+    models::{database_models::User, http_models::FollowsQuery},
+};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 100;
+
+pub async fn follow(ctx: &HttpContext, user: AuthUser, username: String) -> HttpResult<()> {
+    let to_user_id = get_id(&ctx.pool, &username).await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO "user_subscribe_user" ("from_user_id", "to_user_id")
+        VALUES ($1, $2)
+        ON CONFLICT DO NOTHING
+        "#,
+        user.user_id,
+        to_user_id
+    )
+    .execute(&ctx.pool)
+    .await
+    .on_constraint("user_subscribe_user_check", |_| {
+        HttpError::bad_request("You can not follow yourself")
+    })?;
+
+    Ok(())
+}
+
+pub async fn unfollow(ctx: &HttpContext, user: AuthUser, username: String) -> HttpResult<()> {
+    let to_user_id = get_id(&ctx.pool, &username).await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM "user_subscribe_user"
+        WHERE "from_user_id" = $1
+        AND "to_user_id" = $2
+        "#,
+        user.user_id,
+        to_user_id
+    )
+    .execute(&ctx.pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Users who follow the user
+pub async fn followers(
+    ctx: &HttpContext,
+    username: String,
+    query: FollowsQuery,
+) -> HttpResult<Vec<User>> {
+    let user_id = get_id(&ctx.pool, &username).await?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let mut users = sqlx::query_as!(
+        User,
+        r#"
+        SELECT u."id", u."username", u."display_name", u."avatar", u."status",
+            FALSE AS "online!", NULL::TIMESTAMPTZ AS "last_seen", u."hide_last_seen"
+        FROM "user_subscribe_user" s
+        JOIN "user" u ON u."id" = s."from_user_id"
+        WHERE s."to_user_id" = $1
+        AND (
+            $2::UUID IS NULL
+            OR (s."created_at", s."from_user_id") < (
+                SELECT "created_at", "from_user_id" FROM "user_subscribe_user"
+                WHERE "to_user_id" = $1
+                AND "from_user_id" = $2
+            )
+        )
+        ORDER BY s."created_at" DESC, s."from_user_id" DESC
+        LIMIT $3
+        "#,
+        user_id,
+        query.before,
+        limit
+    )
+    .fetch_all(&ctx.pool)
+    .await?;
+
+    presence::fill(ctx, &mut users).await?;
+    Ok(users)
+}
+
+/// Users who the user follows
+pub async fn following(
+    ctx: &HttpContext,
+    username: String,
+    query: FollowsQuery,
+) -> HttpResult<Vec<User>> {
+    let user_id = get_id(&ctx.pool, &username).await?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let mut users = sqlx::query_as!(
+        User,
+        r#"
+        SELECT u."id", u."username", u."display_name", u."avatar", u."status",
+            FALSE AS "online!", NULL::TIMESTAMPTZ AS "last_seen", u."hide_last_seen"
+        FROM "user_subscribe_user" s
+        JOIN "user" u ON u."id" = s."to_user_id"
+        WHERE s."from_user_id" = $1
+        AND (
+            $2::UUID IS NULL
+            OR (s."created_at", s."to_user_id") < (
+                SELECT "created_at", "to_user_id" FROM "user_subscribe_user"
+                WHERE "from_user_id" = $1
+                AND "to_user_id" = $2
+            )
+        )
+        ORDER BY s."created_at" DESC, s."to_user_id" DESC
+        LIMIT $3
+        "#,
+        user_id,
+        query.before,
+        limit
+    )
+    .fetch_all(&ctx.pool)
+    .await?;
+
+    presence::fill(ctx, &mut users).await?;
+    Ok(users)
+}
+
+/// Users who the user follows and who follow the user back
+pub async fn mutuals(
+    ctx: &HttpContext,
+    username: String,
+    query: FollowsQuery,
+) -> HttpResult<Vec<User>> {
+    let user_id = get_id(&ctx.pool, &username).await?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    let mut users = sqlx::query_as!(
+        User,
+        r#"
+        SELECT u."id", u."username", u."display_name", u."avatar", u."status",
+            FALSE AS "online!", NULL::TIMESTAMPTZ AS "last_seen", u."hide_last_seen"
+        FROM "user_subscribe_user" s
+        JOIN "user_subscribe_user" b ON b."from_user_id" = s."to_user_id" AND b."to_user_id" = s."from_user_id"
+        JOIN "user" u ON u."id" = s."to_user_id"
+        WHERE s."from_user_id" = $1
+        AND (
+            $2::UUID IS NULL
+            OR (s."created_at", s."to_user_id") < (
+                SELECT "created_at", "to_user_id" FROM "user_subscribe_user"
+                WHERE "from_user_id" = $1
+                AND "to_user_id" = $2
+            )
+        )
+        ORDER BY s."created_at" DESC, s."to_user_id" DESC
+        LIMIT $3
+        "#,
+        user_id,
+        query.before,
+        limit
+    )
+    .fetch_all(&ctx.pool)
+    .await?;
+
+    presence::fill(ctx, &mut users).await?;
+    Ok(users)
+}
@@ -0,0 +1,162 @@
+//! WebSocket connections of users.
+//! Every connection receives events from [HttpContext::events]
+//! and only forwards those addressed to its user.
+//!
+//! Connections are closed when the access token expires,
+//! and when the session is revoked, which is checked every [presence::HEARTBEAT].
+//! Clients have to reconnect with a new access token.
+
+use std::{sync::Arc, time::Duration};
+use axum::extract::ws::{CloseFrame, Message, WebSocket};
+use futures_util::{stream::SplitStream, SinkExt, StreamExt};
+use time::OffsetDateTime;
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+use crate::{
+    http::{AuthUser, HttpContext},
+    logic::{chat::ensure_member, event, presence, session},
+    models::http_models::{ClientEvent, Event},
+};
+
+/// Time to send [ClientEvent::Authenticate] after connecting
+const AUTHENTICATE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Close code for connections without a valid session, client has to get a new access token
+const UNAUTHORIZED: u16 = 4001;
+/// Typing events of a user in the same chat are dropped if they come more often,
+/// clients usually send one on every key press
+const TYPING_INTERVAL: Duration = Duration::from_secs(3);
+
+fn close_unauthorized(reason: &'static str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code: UNAUTHORIZED,
+        reason: reason.into(),
+    }))
+}
+
+/// Waits for [ClientEvent::Authenticate], access tokens are not put into urls,
+/// because they end up in logs
+async fn authenticate(ctx: &HttpContext, receiver: &mut SplitStream<WebSocket>) -> Option<AuthUser> {
+    let message = tokio::time::timeout(AUTHENTICATE_TIMEOUT, receiver.next()).await;
+    let Ok(Some(Ok(Message::Text(text)))) = message else {
+        return None;
+    };
+    let Ok(ClientEvent::Authenticate { token }) = serde_json::from_str(&text) else {
+        return None;
+    };
+    AuthUser::from_token(ctx, &token).await.ok()
+}
+
+pub async fn handle(ctx: Arc<HttpContext>, user: Option<AuthUser>, socket: WebSocket) {
+    let (mut sender, mut receiver) = socket.split();
+    let user = match user {
+        Some(user) => user,
+        None => match authenticate(&ctx, &mut receiver).await {
+            Some(user) => user,
+            None => {
+                let _ = sender.send(close_unauthorized("Authentication failed")).await;
+                return;
+            }
+        },
+    };
+
+    let user_id = user.user_id;
+    let connection_id = Uuid::new_v4();
+    let mut events = ctx.events.subscribe();
+
+    let expires_in = user.expires_at - OffsetDateTime::now_utc().unix_timestamp();
+    let expiry = tokio::time::sleep(Duration::from_secs(expires_in.max(0) as u64));
+    tokio::pin!(expiry);
+
+    if presence::connect(&ctx, user_id, connection_id).await {
+        event::publish_to_contacts(&ctx, user_id, Event::Presence { user_id, online: true }).await;
+    }
+
+    let mut heartbeat = tokio::time::interval(presence::HEARTBEAT);
+    // the first tick completes immediately, connection was just registered
+    heartbeat.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = &mut expiry => {
+                let _ = sender.send(close_unauthorized("Access token expired")).await;
+                break;
+            }
+            _ = heartbeat.tick() => {
+                // if the check fails, the connection is kept until the next one
+                if let Ok(false) = session::is_active(&ctx, &user).await {
+                    let _ = sender.send(close_unauthorized("Session was revoked")).await;
+                    break;
+                }
+                presence::heartbeat(&ctx, user_id, connection_id).await;
+            }
+            event = events.recv() => match event {
+                Ok(event) => {
+                    if !event.recipients.contains(&user_id) {
+                        continue;
+                    }
+                    if sender.send(Message::Text(event.payload.clone())).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("WebSocket connection of {user_id} skipped {skipped} events");
+                }
+                Err(RecvError::Closed) => break,
+            },
+            message = receiver.next() => match message {
+                Some(Ok(Message::Text(text))) => handle_client_event(&ctx, user_id, &text).await,
+                // pings are answered automatically
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    if presence::disconnect(&ctx, user_id, connection_id).await {
+        event::publish_to_contacts(&ctx, user_id, Event::Presence { user_id, online: false }).await;
+    }
+}
+
+/// Only the first typing event in [TYPING_INTERVAL] is let through,
+/// it is checked in Redis, so it works across all connections of the user
+async fn typing_allowed(ctx: &HttpContext, user_id: Uuid, chat_id: Uuid) -> bool {
+    let result: redis::RedisResult<Option<String>> = redis::cmd("SET")
+        .arg(format!("typing:{user_id}:{chat_id}"))
+        .arg(1)
+        .arg("NX")
+        .arg("PX")
+        .arg(TYPING_INTERVAL.as_millis() as u64)
+        .query_async(&mut ctx.redis.clone())
+        .await;
+
+    match result {
+        Ok(set) => set.is_some(),
+        Err(e) => {
+            log::error!("failed to throttle typing event: {e:?}");
+            false
+        }
+    }
+}
+
+/// Invalid events are ignored, there is nobody to return an error to
+async fn handle_client_event(ctx: &HttpContext, user_id: Uuid, text: &str) {
+    let Ok(client_event) = serde_json::from_str::<ClientEvent>(text) else {
+        log::info!("Invalid WebSocket event from {user_id}");
+        return;
+    };
+
+    match client_event {
+        // connection is already authenticated
+        ClientEvent::Authenticate { .. } => {}
+        ClientEvent::Typing { chat_id } => {
+            if !typing_allowed(ctx, user_id, chat_id).await {
+                return;
+            }
+            if ensure_member(&ctx.pool, chat_id, user_id).await.is_err() {
+                return;
+            }
+            event::publish_to_chat(ctx, chat_id, Event::Typing { chat_id, user_id }).await;
+        }
+    }
+}
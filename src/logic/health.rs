@@ -8,7 +8,7 @@ pub async fn check_health(
     ctx: &HttpContext
 ) -> Health {
     let postgres = check_postgres_health(&ctx.pool).await;
-    let redis = check_redis_health(&mut *ctx.redis.lock().await).await;
+    let redis = check_redis_health(&mut ctx.redis.clone()).await;
     let third_party = check_ip_api_health(&ctx.client).await;
 
     let status = postgres.status && redis.status && third_party.status;
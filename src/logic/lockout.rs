@@ -0,0 +1,60 @@
+//! Protection of login from guessing passwords and codes.
+//! Failed attempts are counted in Redis, key says what is counted, e.g. account and ip address.
+//! After a few of them, every next failure locks the login for twice as long.
+
+use std::time::Duration;
+use redis::AsyncCommands;
+
+use crate::http::{HttpContext, HttpError, HttpResult};
+
+/// Failures allowed before the first lock
+const FREE_ATTEMPTS: u32 = 5;
+const BASE_LOCK: Duration = Duration::from_secs(1);
+const MAX_LOCK: Duration = Duration::from_secs(60 * 60);
+/// Failures are forgotten if there were none for this long
+const FAILURES_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn failures_key(key: &str) -> String {
+    format!("lockout:failures:{key}")
+}
+
+fn lock_key(key: &str) -> String {
+    format!("lockout:lock:{key}")
+}
+
+fn lock_time(failures: u32) -> Duration {
+    let exponent = failures.saturating_sub(FREE_ATTEMPTS).min(31);
+    BASE_LOCK.saturating_mul(2_u32.pow(exponent)).min(MAX_LOCK)
+}
+
+/// Returns [HttpError::TooManyRequests] if login is locked
+pub async fn check(ctx: &HttpContext, key: &str) -> HttpResult<()> {
+    let ttl: i64 = ctx.redis.clone().ttl(lock_key(key)).await?;
+    if ttl > 0 {
+        return Err(HttpError::too_many_requests(ttl as u64));
+    }
+    Ok(())
+}
+
+/// Counts the failure, locks the login if there were too many of them
+pub async fn fail(ctx: &HttpContext, key: &str) -> HttpResult<()> {
+    let mut redis = ctx.redis.clone();
+    let (failures, _): (u32, ()) = redis::pipe()
+        .incr(failures_key(key), 1)
+        .expire(failures_key(key), FAILURES_TTL.as_secs() as i64)
+        .query_async(&mut redis)
+        .await?;
+
+    if failures >= FREE_ATTEMPTS {
+        let lock = lock_time(failures);
+        redis.set_ex::<_, _, ()>(lock_key(key), failures, lock.as_secs()).await?;
+        log::warn!("login locked for {} seconds after {failures} failures: {key}", lock.as_secs());
+    }
+    Ok(())
+}
+
+pub async fn reset(ctx: &HttpContext, key: &str) -> HttpResult<()> {
+    ctx.redis.clone().del::<_, ()>(failures_key(key)).await?;
+    Ok(())
+}
+
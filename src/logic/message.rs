@@ -0,0 +1,357 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    http::{AuthUser, HttpError, HttpErrorContext, HttpResult, HttpContext},
+    logic::{chat::ensure_member, event},
+    models::{
+        database_models::{Message, MessageRead, MessageRow},
+        http_models::{EditMessageBody, Event, MessagesQuery, SendMessageBody},
+    },
+};
+
+const DEFAULT_LIMIT: i64 = 50;
+const MAX_LIMIT: i64 = 100;
+
+async fn get_message(pool: &PgPool, chat_id: Uuid, message_id: Uuid) -> HttpResult<Message> {
+    let message = sqlx::query_as!(
+        MessageRow,
+        r#"
+        SELECT
+            m."id", m."chat_id", m."sender_id", m."context", m."edited", m."created_at", m."updated_at",
+            r."id" AS "reply_id?", r."sender_id" AS "reply_sender_id?", r."context" AS "reply_context?",
+            f."id" AS "forward_id?", f."sender_id" AS "forward_sender_id?", f."context" AS "forward_context?"
+        FROM "message" m
+        LEFT JOIN "message" r ON r."id" = m."reply_message_id"
+        LEFT JOIN "message" f ON f."id" = m."forward_message_id"
+        WHERE m."id" = $1
+        AND m."chat_id" = $2
+        "#,
+        message_id,
+        chat_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .http_context(HttpError::not_found("Message not found"))?;
+
+    Ok(message.into())
+}
+
+/// Returns [HttpError::Forbidden] if user did not send the message.
+async fn ensure_sender(
+    pool: &PgPool,
+    chat_id: Uuid,
+    message_id: Uuid,
+    user_id: Uuid,
+) -> HttpResult<()> {
+    let sender_id = sqlx::query!(
+        r#"
+        SELECT "sender_id" FROM "message"
+        WHERE "id" = $1
+        AND "chat_id" = $2
+        "#,
+        message_id,
+        chat_id
+    )
+    .fetch_optional(pool)
+    .await?
+    .http_context(HttpError::not_found("Message not found"))?
+    .sender_id;
+
+    if sender_id != user_id {
+        return Err(HttpError::Forbidden);
+    }
+    Ok(())
+}
+
+pub async fn list(
+    ctx: &HttpContext,
+    user: AuthUser,
+    chat_id: Uuid,
+    query: MessagesQuery,
+) -> HttpResult<Vec<Message>> {
+    ensure_member(&ctx.pool, chat_id, user.user_id).await?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    // cursor from another chat would silently return the whole chat instead
+    if let Some(before) = query.before {
+        let exists = sqlx::query!(
+            r#"
+            SELECT EXISTS(
+                SELECT 1 FROM "message"
+                WHERE "id" = $1
+                AND "chat_id" = $2
+            ) AS "exists!"
+            "#,
+            before,
+            chat_id
+        )
+        .fetch_one(&ctx.pool)
+        .await?
+        .exists;
+
+        if !exists {
+            return Err(HttpError::bad_request("Message `before` is not in this chat"));
+        }
+    }
+
+    let messages = sqlx::query_as!(
+        MessageRow,
+        r#"
+        SELECT
+            m."id", m."chat_id", m."sender_id", m."context", m."edited", m."created_at", m."updated_at",
+            r."id" AS "reply_id?", r."sender_id" AS "reply_sender_id?", r."context" AS "reply_context?",
+            f."id" AS "forward_id?", f."sender_id" AS "forward_sender_id?", f."context" AS "forward_context?"
+        FROM "message" m
+        LEFT JOIN "message" r ON r."id" = m."reply_message_id"
+        LEFT JOIN "message" f ON f."id" = m."forward_message_id"
+        WHERE m."chat_id" = $1
+        AND (
+            $2::UUID IS NULL
+            OR (m."created_at", m."id") < (
+                SELECT "created_at", "id" FROM "message"
+                WHERE "id" = $2
+                AND "chat_id" = $1
+            )
+        )
+        ORDER BY m."created_at" DESC, m."id" DESC
+        LIMIT $3
+        "#,
+        chat_id,
+        query.before,
+        limit
+    )
+    .fetch_all(&ctx.pool)
+    .await?
+    .into_iter()
+    .map(Message::from)
+    .collect();
+
+    Ok(messages)
+}
+
+pub async fn send(
+    ctx: &HttpContext,
+    user: AuthUser,
+    chat_id: Uuid,
+    body: SendMessageBody,
+) -> HttpResult<Message> {
+    ensure_member(&ctx.pool, chat_id, user.user_id).await?;
+
+    if body.context.is_none() && body.forward_message_id.is_none() {
+        return Err(HttpError::bad_request("Message must have a text or forward another message"));
+    }
+
+    if let Some(reply_message_id) = body.reply_message_id {
+        let exists = sqlx::query!(
+            r#"
+            SELECT COUNT(1) FROM "message"
+            WHERE "id" = $1
+            AND "chat_id" = $2
+            "#,
+            reply_message_id,
+            chat_id
+        )
+        .fetch_one(&ctx.pool)
+        .await?
+        .count
+            == Some(1);
+
+        if !exists {
+            return Err(HttpError::bad_request("Replied message not found"));
+        }
+    }
+
+    // Forwarding a forwarded message points to the original one.
+    // The user must be able to see the message they forward.
+    let forward_message_id = match body.forward_message_id {
+        Some(forward_message_id) => Some(
+            sqlx::query!(
+                r#"
+                SELECT COALESCE(m."forward_message_id", m."id") AS "id!"
+                FROM "message" m
+                JOIN "chat_user" cu ON cu."chat_id" = m."chat_id"
+                WHERE m."id" = $1
+                AND cu."user_id" = $2
+                "#,
+                forward_message_id,
+                user.user_id
+            )
+            .fetch_optional(&ctx.pool)
+            .await?
+            .http_context(HttpError::bad_request("Forwarded message not found"))?
+            .id
+        ),
+        None => None,
+    };
+
+    let message_id = sqlx::query!(
+        r#"
+        INSERT INTO "message" (
+            "chat_id",
+            "sender_id",
+            "reply_message_id",
+            "forward_message_id",
+            "context"
+        ) VALUES ($1, $2, $3, $4, $5)
+        RETURNING "id"
+        "#,
+        chat_id,
+        user.user_id,
+        body.reply_message_id,
+        forward_message_id,
+        body.context
+    )
+    .fetch_one(&ctx.pool)
+    .await?
+    .id;
+
+    let message = get_message(&ctx.pool, chat_id, message_id).await?;
+    event::publish_to_chat(ctx, chat_id, Event::MessageCreated { message: message.clone() }).await;
+
+    Ok(message)
+}
+
+pub async fn edit(
+    ctx: &HttpContext,
+    user: AuthUser,
+    chat_id: Uuid,
+    message_id: Uuid,
+    body: EditMessageBody,
+) -> HttpResult<Message> {
+    ensure_member(&ctx.pool, chat_id, user.user_id).await?;
+    ensure_sender(&ctx.pool, chat_id, message_id, user.user_id).await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE "message"
+        SET
+            "context" = $2,
+            "edited" = TRUE,
+            "updated_at" = NOW()
+        WHERE "id" = $1
+        "#,
+        message_id,
+        body.context
+    )
+    .execute(&ctx.pool)
+    .await?;
+
+    let message = get_message(&ctx.pool, chat_id, message_id).await?;
+    event::publish_to_chat(ctx, chat_id, Event::MessageEdited { message: message.clone() }).await;
+
+    Ok(message)
+}
+
+pub async fn delete(
+    ctx: &HttpContext,
+    user: AuthUser,
+    chat_id: Uuid,
+    message_id: Uuid,
+) -> HttpResult<()> {
+    ensure_member(&ctx.pool, chat_id, user.user_id).await?;
+    ensure_sender(&ctx.pool, chat_id, message_id, user.user_id).await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM "message"
+        WHERE "id" = $1
+        "#,
+        message_id
+    )
+    .execute(&ctx.pool)
+    .await?;
+
+    event::publish_to_chat(ctx, chat_id, Event::MessageDeleted { chat_id, message_id }).await;
+
+    Ok(())
+}
+
+/// Marks the message and every message before it as read.
+/// Only moves forward, marking an older message does nothing.
+pub async fn mark_read(
+    ctx: &HttpContext,
+    user: AuthUser,
+    chat_id: Uuid,
+    message_id: Uuid,
+) -> HttpResult<()> {
+    ensure_member(&ctx.pool, chat_id, user.user_id).await?;
+
+    let message = sqlx::query!(
+        r#"
+        SELECT "id", "created_at" FROM "message"
+        WHERE "id" = $1
+        AND "chat_id" = $2
+        "#,
+        message_id,
+        chat_id
+    )
+    .fetch_optional(&ctx.pool)
+    .await?
+    .http_context(HttpError::not_found("Message not found"))?;
+
+    let moved = sqlx::query!(
+        r#"
+        UPDATE "chat_user" cu
+        SET
+            "last_read_message_id" = $3,
+            "last_read_at" = NOW()
+        WHERE cu."user_id" = $1
+        AND cu."chat_id" = $2
+        AND (
+            cu."last_read_message_id" IS NULL
+            OR ($4::TIMESTAMPTZ, $3::UUID) > (
+                SELECT "created_at", "id" FROM "message"
+                WHERE "id" = cu."last_read_message_id"
+            )
+        )
+        "#,
+        user.user_id,
+        chat_id,
+        message.id,
+        message.created_at
+    )
+    .execute(&ctx.pool)
+    .await?
+    .rows_affected();
+
+    if moved == 0 {
+        return Ok(());
+    }
+    let event = Event::MessageRead { chat_id, user_id: user.user_id, message_id };
+    event::publish_to_chat(ctx, chat_id, event).await;
+
+    Ok(())
+}
+
+/// Members who have read this message or a newer one,
+/// `created_at` is when they last marked something as read in the chat.
+pub async fn read_by(
+    ctx: &HttpContext,
+    user: AuthUser,
+    chat_id: Uuid,
+    message_id: Uuid,
+) -> HttpResult<Vec<MessageRead>> {
+    ensure_member(&ctx.pool, chat_id, user.user_id).await?;
+
+    let reads = sqlx::query_as!(
+        MessageRead,
+        r#"
+        SELECT cu."user_id", cu."last_read_at" AS "created_at!"
+        FROM "chat_user" cu
+        JOIN "message" m ON m."id" = $1 AND m."chat_id" = cu."chat_id"
+        JOIN "message" r ON r."id" = cu."last_read_message_id"
+        WHERE cu."chat_id" = $2
+        AND cu."user_id" != m."sender_id"
+        AND (r."created_at", r."id") >= (m."created_at", m."id")
+        ORDER BY cu."last_read_at"
+        "#,
+        message_id,
+        chat_id
+    )
+    .fetch_all(&ctx.pool)
+    .await?;
+
+    Ok(reads)
+}
@@ -0,0 +1,419 @@
+//! Logging in with external identity providers (OpenID Connect).
+//! Login state and PKCE verifier are kept in Redis between redirects.
+//! Provider accounts are linked to users, new users are registered on the first login.
+//! Existing users are never linked by e-mail automatically, they have to do it themselves.
+
+use anyhow::Context;
+use rand::Rng;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    http::{AuthUser, RequestInfo, HttpError, HttpErrorContext, HttpResult, HttpContext, ResultExt},
+    logic::auth,
+    models::{
+        database_models::UserIdentity,
+        http_models::{AuthorizationUrl, LoginResponse, OidcCallbackBody},
+    },
+    utils::oidc::{self, IdTokenClaims, Pkce, Provider},
+};
+
+/// Seconds user has to log in with the provider
+const STATE_LIFE_TIME: u64 = 10 * 60;
+/// Same as the limit of display names set by users
+const DISPLAY_NAME_MAX_LENGTH: usize = 64;
+
+#[derive(Serialize, Deserialize)]
+struct PendingLogin {
+    provider: String,
+    verifier: String,
+    nonce: String,
+    /// User who links the provider, not set when logging in
+    user_id: Option<Uuid>,
+}
+
+fn state_key(state: &str) -> String {
+    format!("oidc:state:{state}")
+}
+
+fn provider(name: &str) -> HttpResult<&'static Provider> {
+    oidc::provider(name).http_context(HttpError::not_found("Provider not found"))
+}
+
+pub fn providers() -> Vec<String> {
+    oidc::providers().map(|provider| provider.name.clone()).collect()
+}
+
+async fn start(
+    ctx: &HttpContext,
+    provider_name: &str,
+    user_id: Option<Uuid>,
+) -> HttpResult<AuthorizationUrl> {
+    let provider = provider(provider_name)?;
+    let state = oidc::random_string();
+    let nonce = oidc::random_string();
+    let pkce = Pkce::generate();
+
+    let url = provider
+        .authorization_url(&ctx.client, &state, &nonce, &pkce.challenge)
+        .await?;
+
+    let pending = PendingLogin {
+        provider: provider.name.clone(),
+        verifier: pkce.verifier,
+        nonce,
+        user_id,
+    };
+    let pending = serde_json::to_string(&pending).context("failed to serialize login state")?;
+    ctx.redis
+        .clone()
+        .set_ex::<_, _, ()>(state_key(&state), pending, STATE_LIFE_TIME)
+        .await?;
+
+    Ok(AuthorizationUrl { url })
+}
+
+/// State can only be used once and only in the flow it was created for
+async fn finish(
+    ctx: &HttpContext,
+    body: OidcCallbackBody,
+    user_id: Option<Uuid>,
+) -> HttpResult<(&'static Provider, IdTokenClaims)> {
+    let invalid = || HttpError::bad_request("Login state is invalid or expired");
+
+    let pending: Option<String> = ctx.redis.clone().get_del(state_key(&body.state)).await?;
+    let pending: PendingLogin = serde_json::from_str(&pending.http_context(invalid())?)
+        .context("failed to parse login state")?;
+    if pending.user_id != user_id {
+        return Err(invalid());
+    }
+
+    let provider = provider(&pending.provider)?;
+    let claims = provider
+        .exchange(&ctx.client, &body.code, &pending.verifier, &pending.nonce)
+        .await?;
+
+    Ok((provider, claims))
+}
+
+/// Returns a free username based on the one from the provider or e-mail
+async fn new_username(ctx: &HttpContext, claims: &IdTokenClaims, email: &str) -> HttpResult<String> {
+    let base: String = claims
+        .preferred_username
+        .as_deref()
+        .unwrap_or(email.split('@').next().unwrap_or_default())
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .take(19)
+        .collect::<String>()
+        .to_lowercase();
+    let base = format!("{base:_<3}");
+
+    let mut username = base.clone();
+    for _ in 0..5 {
+        if !auth::username_exists(&ctx.pool, &username).await? {
+            return Ok(username);
+        }
+        username = format!("{base}_{:04}", rand::thread_rng().gen_range(0..10000));
+    }
+    Err(HttpError::bad_request("Failed to choose a username, register with a password instead"))
+}
+
+/// Registers a new user linked to the provider account.
+/// Provider must have verified the e-mail.
+async fn register(
+    ctx: &HttpContext,
+    provider: &Provider,
+    claims: &IdTokenClaims,
+) -> HttpResult<Uuid> {
+    let email = match &claims.email {
+        Some(email) if claims.email_verified => email.to_lowercase(),
+        _ => return Err(HttpError::bad_request("Provider did not share a verified e-mail")),
+    };
+    if auth::email_exists(&ctx.pool, &email).await? {
+        return Err(HttpError::bad_request(
+            "Account with this e-mail already exists, log in and link the provider to it",
+        ));
+    }
+    let username = new_username(ctx, claims, &email).await?;
+    let display_name = match claims.name.as_deref().map(str::trim) {
+        Some(name) if !name.is_empty() => name.chars().take(DISPLAY_NAME_MAX_LENGTH).collect(),
+        _ => username.clone(),
+    };
+
+    let mut tx = ctx.pool.begin().await?;
+
+    let user_id = sqlx::query!(
+        r#"
+        INSERT INTO "user" ("username", "email", "display_name", "email_verified_at")
+        VALUES ($1, $2, $3, NOW())
+        RETURNING "id"
+        "#,
+        username,
+        email,
+        display_name
+    )
+    .fetch_one(&mut *tx)
+    .await
+    .on_constraint("user_username_key", |_| {
+        HttpError::bad_request("Username is already taken")
+    })
+    .on_constraint("user_email_key", |_| {
+        HttpError::bad_request("Email is already taken")
+    })?
+    .id;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO "user_identity" ("provider", "subject", "user_id", "email")
+        VALUES ($1, $2, $3, $4)
+        "#,
+        provider.name,
+        claims.sub,
+        user_id,
+        email
+    )
+    .execute(&mut *tx)
+    .await
+    // another callback of the same account registered it first
+    .on_constraint("user_identity_pkey", |_| {
+        HttpError::bad_request("Account is already registered, log in again")
+    })?;
+
+    tx.commit().await?;
+
+    Ok(user_id)
+}
+
+pub async fn authorize(ctx: &HttpContext, provider: &str) -> HttpResult<AuthorizationUrl> {
+    start(ctx, provider, None).await
+}
+
+pub async fn login(
+    ctx: &HttpContext,
+    body: OidcCallbackBody,
+    info: RequestInfo,
+) -> HttpResult<LoginResponse> {
+    let (provider, claims) = finish(ctx, body, None).await?;
+
+    let user_id = sqlx::query!(
+        r#"
+        SELECT "user_id" FROM "user_identity"
+        WHERE "provider" = $1
+        AND "subject" = $2
+        "#,
+        provider.name,
+        claims.sub
+    )
+    .fetch_optional(&ctx.pool)
+    .await?
+    .map(|identity| identity.user_id);
+
+    let user_id = match user_id {
+        Some(user_id) => user_id,
+        None => register(ctx, provider, &claims).await?,
+    };
+
+    let user = auth::get_user(&ctx.pool, user_id).await?;
+    auth::finish_login(ctx, user, info).await
+}
+
+pub async fn list(ctx: &HttpContext, user: AuthUser) -> HttpResult<Vec<UserIdentity>> {
+    let identities = sqlx::query_as!(
+        UserIdentity,
+        r#"
+        SELECT "provider", "email", "created_at"
+        FROM "user_identity"
+        WHERE "user_id" = $1
+        ORDER BY "created_at"
+        "#,
+        user.user_id
+    )
+    .fetch_all(&ctx.pool)
+    .await?;
+
+    Ok(identities)
+}
+
+pub async fn authorize_link(
+    ctx: &HttpContext,
+    user: AuthUser,
+    provider: &str,
+) -> HttpResult<AuthorizationUrl> {
+    start(ctx, provider, Some(user.user_id)).await
+}
+
+/// Links account of the provider to the user, it must not be linked to anyone yet
+async fn insert_identity(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    provider: &str,
+    subject: &str,
+    email: Option<String>,
+) -> HttpResult<UserIdentity> {
+    let identity = sqlx::query_as!(
+        UserIdentity,
+        r#"
+        INSERT INTO "user_identity" ("provider", "subject", "user_id", "email")
+        VALUES ($1, $2, $3, $4)
+        RETURNING "provider", "email", "created_at"
+        "#,
+        provider,
+        subject,
+        user_id,
+        email.map(|email| email.to_lowercase())
+    )
+    .fetch_one(executor)
+    .await
+    .on_constraint("user_identity_pkey", |_| {
+        HttpError::bad_request("This account is already linked to another user")
+    })
+    .on_constraint("user_identity_user_id_provider_key", |_| {
+        HttpError::bad_request("Provider is already linked")
+    })?;
+
+    Ok(identity)
+}
+
+pub async fn link(
+    ctx: &HttpContext,
+    user: AuthUser,
+    body: OidcCallbackBody,
+) -> HttpResult<UserIdentity> {
+    let (provider, claims) = finish(ctx, body, Some(user.user_id)).await?;
+    insert_identity(&ctx.pool, user.user_id, &provider.name, &claims.sub, claims.email).await
+}
+
+/// User must still be able to log in after it, with a password or another provider
+async fn delete_identity(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    provider: &str,
+) -> HttpResult<()> {
+    let other_logins = sqlx::query!(
+        r#"
+        SELECT
+            "password_hash" IS NOT NULL AS "has_password!",
+            (
+                SELECT COUNT(1) FROM "user_identity"
+                WHERE "user_id" = u."id"
+                AND "provider" != $2
+            ) AS "identities!"
+        FROM "user" u
+        WHERE "id" = $1
+        FOR UPDATE
+        "#,
+        user_id,
+        provider
+    )
+    .fetch_one(&mut **tx)
+    .await?;
+
+    if !other_logins.has_password && other_logins.identities == 0 {
+        return Err(HttpError::bad_request(
+            "Set a password before unlinking the last provider",
+        ));
+    }
+
+    let unlinked = sqlx::query!(
+        r#"
+        DELETE FROM "user_identity"
+        WHERE "user_id" = $1
+        AND "provider" = $2
+        "#,
+        user_id,
+        provider
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+
+    if unlinked == 0 {
+        return Err(HttpError::not_found("Provider is not linked"));
+    }
+    Ok(())
+}
+
+pub async fn unlink(ctx: &HttpContext, user: AuthUser, provider: &str) -> HttpResult<()> {
+    let mut tx = ctx.pool.begin().await?;
+    delete_identity(&mut tx, user.user_id, provider).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::{Acquire, PgPool};
+
+    /// Needs a migrated database, everything is rolled back in the end
+    async fn database() -> PgPool {
+        dotenvy::dotenv().ok();
+        let url = std::env::var("DATABASE_URL").expect("DATABASE_URL env variable is not set");
+        PgPool::connect(&url).await.unwrap()
+    }
+
+    async fn create_user(tx: &mut Transaction<'_, Postgres>) -> Uuid {
+        let username = format!("test_{}", &Uuid::new_v4().simple().to_string()[..12]);
+        sqlx::query_scalar(
+            r#"
+            INSERT INTO "user" ("username", "email", "display_name")
+            VALUES ($1, $1 || '@example.com', $1)
+            RETURNING "id"
+            "#,
+        )
+        .bind(username)
+        .fetch_one(&mut **tx)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    #[ignore = "needs DATABASE_URL"]
+    async fn test_link_and_unlink() {
+        let pool = database().await;
+        let mut tx = pool.begin().await.unwrap();
+        let user_id = create_user(&mut tx).await;
+        let other_user_id = create_user(&mut tx).await;
+        let subject = Uuid::new_v4().to_string();
+
+        let identity = insert_identity(&mut *tx, user_id, "mock", &subject, Some("User@Example.com".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(identity.provider, "mock");
+        assert_eq!(identity.email.as_deref(), Some("user@example.com"));
+
+        // failed statements abort the transaction, so they are tried in a savepoint
+        let mut savepoint = tx.begin().await.unwrap();
+        let linked_to_other = insert_identity(&mut *savepoint, other_user_id, "mock", &subject, None).await;
+        assert!(matches!(linked_to_other, Err(HttpError::BadRequest(_))));
+        drop(savepoint);
+
+        let mut savepoint = tx.begin().await.unwrap();
+        let another_account = insert_identity(&mut *savepoint, user_id, "mock", "another", None).await;
+        assert!(matches!(another_account, Err(HttpError::BadRequest(_))));
+        drop(savepoint);
+
+        // the only way to log in
+        let mut savepoint = tx.begin().await.unwrap();
+        let last_login = delete_identity(&mut savepoint, user_id, "mock").await;
+        assert!(matches!(last_login, Err(HttpError::BadRequest(_))));
+        drop(savepoint);
+
+        sqlx::query(r#"UPDATE "user" SET "password_hash" = 'hash' WHERE "id" = $1"#)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        delete_identity(&mut tx, user_id, "mock").await.unwrap();
+
+        let not_linked = delete_identity(&mut tx, user_id, "mock").await;
+        assert!(matches!(not_linked, Err(HttpError::NotFound(_))));
+        // account of the provider is free again
+        insert_identity(&mut *tx, other_user_id, "mock", &subject, None).await.unwrap();
+
+        tx.rollback().await.unwrap();
+    }
+}
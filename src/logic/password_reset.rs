@@ -0,0 +1,170 @@
+//! Resetting forgotten passwords.
+//! User receives a one-time token by e-mail, which can be exchanged for a new password.
+//! Responses do not depend on whether the account exists, so e-mails can not be enumerated.
+
+use sqlx::{PgPool, Postgres, Transaction};
+use time::{Duration, OffsetDateTime};
+use uuid::Uuid;
+
+use crate::{
+    http::{HttpError, HttpErrorContext, HttpResult, HttpContext},
+    logic::session,
+    models::http_models::{ForgotPasswordBody, ResetPasswordBody},
+    utils::{email, password::hash_password, password_policy, secret_token},
+};
+
+const TOKEN_LIFE_TIME: Duration = Duration::minutes(30);
+
+pub async fn send(address: String, token: String) {
+    let link = format!("{}/reset-password?token={token}", *email::APP_URL);
+    let result = email::send(
+        &address,
+        "Reset your password",
+        format!(
+            "Open this link to set a new password:\n{link}\n\nIt is valid for {} minutes.\nIf it was not you, just ignore this e-mail.",
+            TOKEN_LIFE_TIME.whole_minutes()
+        ),
+    )
+    .await;
+
+    if let Err(e) = result {
+        log::error!("failed to send password reset e-mail: {e:?}");
+    }
+}
+
+/// Only the latest token of the user is valid, so previous ones are deleted
+pub async fn create_token(tx: &mut Transaction<'_, Postgres>, user_id: Uuid) -> HttpResult<String> {
+    let (token, token_hash) = secret_token::generate();
+
+    sqlx::query!(
+        r#"
+        DELETE FROM "password_reset_token"
+        WHERE "user_id" = $1
+        "#,
+        user_id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO "password_reset_token" ("token_hash", "user_id", "expires_at")
+        VALUES ($1, $2, $3)
+        "#,
+        token_hash,
+        user_id,
+        OffsetDateTime::now_utc() + TOKEN_LIFE_TIME
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(token)
+}
+
+/// Everything is done in the background, so response time does not reveal that the account exists
+pub async fn forgot(ctx: &HttpContext, body: ForgotPasswordBody) -> HttpResult<()> {
+    tokio::spawn(send_if_exists(ctx.pool.clone(), body.email.to_lowercase()));
+    Ok(())
+}
+
+async fn send_if_exists(pool: PgPool, email: String) {
+    let result: HttpResult<()> = async {
+        let user = sqlx::query!(
+            r#"
+            SELECT "id", "email" FROM "user"
+            WHERE "email" = $1
+            "#,
+            email
+        )
+        .fetch_optional(&pool)
+        .await?;
+
+        let Some(user) = user else {
+            return Ok(());
+        };
+
+        let mut tx = pool.begin().await?;
+        let token = create_token(&mut tx, user.id).await?;
+        tx.commit().await?;
+
+        send(user.email, token).await;
+        Ok(())
+    }
+    .await;
+
+    if let Err(e) = result {
+        log::error!("failed to create password reset token: {e:?}");
+    }
+}
+
+/// Sets the new password and logs the user out everywhere
+pub async fn reset(ctx: &HttpContext, body: ResetPasswordBody) -> HttpResult<()> {
+    let token_hash = secret_token::hash(&body.token);
+    let invalid = || HttpError::bad_request("Reset link is invalid or expired");
+
+    // token is only used after the new password is accepted
+    let user = sqlx::query!(
+        r#"
+        SELECT u."username", u."email"
+        FROM "password_reset_token" t
+        JOIN "user" u ON u."id" = t."user_id"
+        WHERE t."token_hash" = $1
+        AND t."expires_at" > NOW()
+        "#,
+        token_hash
+    )
+    .fetch_optional(&ctx.pool)
+    .await?
+    .http_context(invalid())?;
+
+    password_policy::check(&body.password, &user.username, &user.email).await?;
+    let password_hash = hash_password(body.password).await?;
+
+    let mut tx = ctx.pool.begin().await?;
+
+    let user_id: Uuid = sqlx::query!(
+        r#"
+        DELETE FROM "password_reset_token"
+        WHERE "token_hash" = $1
+        AND "expires_at" > NOW()
+        RETURNING "user_id"
+        "#,
+        token_hash
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    .http_context(invalid())?
+    .user_id;
+
+    sqlx::query!(
+        r#"
+        UPDATE "user"
+        SET "password_hash" = $2
+        WHERE "id" = $1
+        "#,
+        user_id,
+        password_hash
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let revoked: Vec<Uuid> = sqlx::query!(
+        r#"
+        DELETE FROM "user_session"
+        WHERE "user_id" = $1
+        RETURNING "id"
+        "#,
+        user_id
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|session| session.id)
+    .collect();
+
+    tx.commit().await?;
+
+    session::revoke_tokens(ctx, &revoked).await;
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+//! # Presence of users
+//! Stored in Redis, so it is shared by all server instances.
+//! - Time user was last seen is updated on every authenticated request and by WebSocket connections.
+//! - User is online while they have at least one WebSocket connection.
+//!   Connections renew themselves every [HEARTBEAT], so connections of a crashed server
+//!   stop counting after [TIMEOUT].
+
+use std::time::Duration;
+use redis::AsyncCommands;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    http::{HttpContext, HttpResult},
+    models::{database_models::User, Timestamptz, TimestamptzOption},
+};
+
+/// Hash of user ids to unix timestamps
+const LAST_SEEN_KEY: &str = "presence:last_seen";
+/// How often connections are renewed
+pub const HEARTBEAT: Duration = Duration::from_secs(30);
+/// Connection is considered closed if it was not renewed for this long
+const TIMEOUT: Duration = Duration::from_secs(90);
+
+/// Sorted set of user's connection ids, scored by the time they were renewed
+fn connections_key(user_id: Uuid) -> String {
+    format!("presence:connections:{user_id}")
+}
+
+fn now() -> i64 {
+    OffsetDateTime::now_utc().unix_timestamp()
+}
+
+fn alive_since() -> i64 {
+    now() - TIMEOUT.as_secs() as i64
+}
+
+/// Updates the time user was last seen.
+/// Presence is not critical, so errors are only logged.
+pub async fn touch(ctx: &HttpContext, user_id: Uuid) {
+    let result: redis::RedisResult<()> = ctx
+        .redis
+        .clone()
+        .hset(LAST_SEEN_KEY, user_id.to_string(), now())
+        .await;
+
+    if let Err(e) = result {
+        log::error!("failed to update last seen: {e:?}");
+    }
+}
+
+/// Adds or renews the connection.
+/// Returns number of user's alive connections.
+async fn renew(ctx: &HttpContext, user_id: Uuid, connection_id: Uuid) -> redis::RedisResult<i64> {
+    let key = connections_key(user_id);
+    let (_, _, _, _, count): ((), (), (), (), i64) = redis::pipe()
+        .hset(LAST_SEEN_KEY, user_id.to_string(), now())
+        .zadd(&key, connection_id.to_string(), now())
+        .zrembyscore(&key, "-inf", alive_since())
+        .expire(&key, TIMEOUT.as_secs() as i64)
+        .zcard(&key)
+        .query_async(&mut ctx.redis.clone())
+        .await?;
+
+    Ok(count)
+}
+
+/// Registers a new WebSocket connection.
+/// Returns `true` if user was offline before it.
+pub async fn connect(ctx: &HttpContext, user_id: Uuid, connection_id: Uuid) -> bool {
+    match renew(ctx, user_id, connection_id).await {
+        Ok(count) => count == 1,
+        Err(e) => {
+            log::error!("failed to register connection: {e:?}");
+            false
+        }
+    }
+}
+
+/// Must be called every [HEARTBEAT] while the connection is open
+pub async fn heartbeat(ctx: &HttpContext, user_id: Uuid, connection_id: Uuid) {
+    if let Err(e) = renew(ctx, user_id, connection_id).await {
+        log::error!("failed to renew connection: {e:?}");
+    }
+}
+
+/// Removes the WebSocket connection.
+/// Returns `true` if user has no connections left.
+pub async fn disconnect(ctx: &HttpContext, user_id: Uuid, connection_id: Uuid) -> bool {
+    let key = connections_key(user_id);
+    let result: redis::RedisResult<((), (), i64)> = redis::pipe()
+        .hset(LAST_SEEN_KEY, user_id.to_string(), now())
+        .zrem(&key, connection_id.to_string())
+        .zcount(&key, alive_since(), "+inf")
+        .query_async(&mut ctx.redis.clone())
+        .await;
+
+    match result {
+        Ok((_, _, count)) => count == 0,
+        Err(e) => {
+            log::error!("failed to remove connection: {e:?}");
+            false
+        }
+    }
+}
+
+/// Fills `online` and `last_seen` of the users.
+/// Last seen time is left empty for users who hide it.
+pub async fn fill(ctx: &HttpContext, users: &mut [User]) -> HttpResult<()> {
+    if users.is_empty() {
+        return Ok(());
+    }
+
+    let ids: Vec<String> = users.iter().map(|user| user.id.to_string()).collect();
+    let mut pipe = redis::pipe();
+    for user in users.iter() {
+        pipe.zcount(connections_key(user.id), alive_since(), "+inf");
+    }
+
+    let mut redis = ctx.redis.clone();
+    let last_seen: Vec<Option<i64>> = redis::cmd("HMGET")
+        .arg(LAST_SEEN_KEY)
+        .arg(&ids)
+        .query_async(&mut redis)
+        .await?;
+    let connections: Vec<i64> = pipe.query_async(&mut redis).await?;
+
+    for ((user, last_seen), connections) in users.iter_mut().zip(last_seen).zip(connections) {
+        user.online = connections > 0;
+        user.last_seen = TimestamptzOption(match user.hide_last_seen {
+            true => None,
+            false => last_seen
+                .and_then(|timestamp| OffsetDateTime::from_unix_timestamp(timestamp).ok())
+                .map(Timestamptz),
+        });
+    }
+
+    Ok(())
+}
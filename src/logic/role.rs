@@ -0,0 +1,196 @@
+//! Roles of users and permissions they give.
+//! They are carried in access tokens, so checking them does not need the database.
+//! When roles of a user change, access tokens of the user are revoked,
+//! so the new roles are received with the next refresh.
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    http::{roles::Admin, HttpError, HttpResult, HttpContext, ResultExt, Role as _},
+    logic::{audit::{self, Action}, session},
+    models::database_models::Role,
+    utils::tokens::Grants,
+};
+
+/// Roles and permissions put into access tokens of the user
+pub async fn grants(pool: &PgPool, user_id: Uuid) -> HttpResult<Grants> {
+    let grants = sqlx::query_as!(
+        Grants,
+        r#"
+        SELECT
+            ARRAY(
+                SELECT "role" FROM "user_role"
+                WHERE "user_id" = $1
+                ORDER BY "role"
+            ) AS "roles!",
+            ARRAY(
+                SELECT DISTINCT p."permission"
+                FROM "user_role" r
+                JOIN "role_permission" p ON p."role" = r."role"
+                WHERE r."user_id" = $1
+                ORDER BY p."permission"
+            ) AS "permissions!"
+        "#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(grants)
+}
+
+pub async fn list(ctx: &HttpContext) -> HttpResult<Vec<Role>> {
+    let roles = sqlx::query_as!(
+        Role,
+        r#"
+        SELECT
+            r."name", r."description",
+            ARRAY(
+                SELECT "permission" FROM "role_permission"
+                WHERE "role" = r."name"
+                ORDER BY "permission"
+            ) AS "permissions!"
+        FROM "role" r
+        ORDER BY r."name"
+        "#
+    )
+    .fetch_all(&ctx.pool)
+    .await?;
+
+    Ok(roles)
+}
+
+pub async fn of_user(ctx: &HttpContext, user_id: Uuid) -> HttpResult<Vec<String>> {
+    Ok(grants(&ctx.pool, user_id).await?.roles)
+}
+
+/// Access tokens of the user get new roles after refresh
+async fn revoke_access_tokens(ctx: &HttpContext, user_id: Uuid) -> HttpResult<()> {
+    let sessions: Vec<Uuid> = sqlx::query!(
+        r#"
+        SELECT "id" FROM "user_session"
+        WHERE "user_id" = $1
+        "#,
+        user_id
+    )
+    .fetch_all(&ctx.pool)
+    .await?
+    .into_iter()
+    .map(|session| session.id)
+    .collect();
+
+    session::revoke_tokens(ctx, &sessions).await;
+    Ok(())
+}
+
+pub async fn assign(
+    ctx: &HttpContext,
+    admin_id: Uuid,
+    user_id: Uuid,
+    role: &str,
+) -> HttpResult<()> {
+    let mut tx = ctx.pool.begin().await?;
+
+    let assigned = sqlx::query!(
+        r#"
+        INSERT INTO "user_role" ("user_id", "role", "assigned_by")
+        VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING
+        "#,
+        user_id,
+        role,
+        admin_id
+    )
+    .execute(&mut *tx)
+    .await
+    .on_constraint("user_role_user_id_fkey", |_| {
+        HttpError::not_found("User not found")
+    })
+    .on_constraint("user_role_role_fkey", |_| {
+        HttpError::not_found("Role not found")
+    })?
+    .rows_affected();
+
+    if assigned == 0 {
+        return Ok(());
+    }
+    audit::record(&mut *tx, admin_id, Action::AssignRole, Some(user_id), role).await?;
+    tx.commit().await?;
+
+    log::info!("role {role} assigned to {user_id} by {admin_id}");
+    revoke_access_tokens(ctx, user_id).await
+}
+
+/// The last admin can not be removed, otherwise nobody could assign roles
+pub async fn remove(
+    ctx: &HttpContext,
+    admin_id: Uuid,
+    user_id: Uuid,
+    role: &str,
+) -> HttpResult<()> {
+    let mut tx = ctx.pool.begin().await?;
+
+    // locks admins, so two of them can not remove each other at once
+    let admins = sqlx::query!(
+        r#"
+        SELECT "user_id" FROM "user_role"
+        WHERE "role" = $1
+        FOR UPDATE
+        "#,
+        Admin::NAME
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    if role == Admin::NAME && admins.len() == 1 && admins[0].user_id == user_id {
+        return Err(HttpError::bad_request("The last admin can not be removed"));
+    }
+
+    let removed = sqlx::query!(
+        r#"
+        DELETE FROM "user_role"
+        WHERE "user_id" = $1
+        AND "role" = $2
+        "#,
+        user_id,
+        role
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    if removed == 0 {
+        return Err(HttpError::not_found("User does not have this role"));
+    }
+    audit::record(&mut *tx, admin_id, Action::RemoveRole, Some(user_id), role).await?;
+    tx.commit().await?;
+
+    log::info!("role {role} removed from {user_id} by {admin_id}");
+    revoke_access_tokens(ctx, user_id).await
+}
+
+/// Used by `grant-role` command, e.g. to create the first admin
+pub async fn assign_by_username(pool: &PgPool, username: &str, role: &str) -> HttpResult<()> {
+    let assigned = sqlx::query!(
+        r#"
+        INSERT INTO "user_role" ("user_id", "role")
+        SELECT "id", $2 FROM "user"
+        WHERE "username" = $1
+        ON CONFLICT DO NOTHING
+        "#,
+        username,
+        role
+    )
+    .execute(pool)
+    .await
+    .on_constraint("user_role_role_fkey", |_| {
+        HttpError::not_found("Role not found")
+    })?
+    .rows_affected();
+
+    if assigned == 0 {
+        return Err(HttpError::bad_request("User not found or already has the role"));
+    }
+    Ok(())
+}
@@ -0,0 +1,222 @@
+//! Sessions of users, one for every logged in device.
+//! Checking them is on the path of every authenticated request, so Postgres is not asked every time:
+//! - Result of the check is cached in Redis until the access token expires,
+//!   deleted sessions are marked as revoked there, so their tokens stop working immediately.
+//! - Time of the last activity is collected in memory and written in batches every [LAST_ACTIVE_INTERVAL].
+
+use std::{collections::HashMap, sync::{Arc, Mutex}, time::Duration};
+use redis::AsyncCommands;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    http::{AuthUser, HttpError, HttpResult, HttpContext},
+    models::database_models::Session,
+    utils::tokens::ACCESS_LIFE_TIME,
+};
+
+const VALID: &str = "valid";
+const REVOKED: &str = "revoked";
+const LAST_ACTIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Sessions used since the last write, with the time they were last used
+pub type LastActive = Arc<Mutex<HashMap<Uuid, OffsetDateTime>>>;
+
+/// [VALID] or [REVOKED], kept while access tokens of the session can be valid
+fn cache_key(session_id: Uuid) -> String {
+    format!("session:{session_id}")
+}
+
+/// Whether the session of the access token still exists and the user is not suspended.
+/// If Redis is unavailable, Postgres is asked.
+/// Suspending deletes sessions of the user, so cached results do not outlive it.
+pub async fn is_active(ctx: &HttpContext, user: &AuthUser) -> HttpResult<bool> {
+    let key = cache_key(user.session_id);
+    let cached: redis::RedisResult<Option<String>> = ctx.redis.clone().get(&key).await;
+    match cached {
+        Ok(Some(state)) => return Ok(state == VALID),
+        Ok(None) => {}
+        Err(e) => log::error!("failed to check cached session: {e:?}"),
+    }
+
+    let active = sqlx::query!(
+        r#"
+        SELECT COUNT(1)
+        FROM "user_session"
+        WHERE "id" = $1
+        AND "user_id" = $2
+        AND NOT EXISTS (
+            SELECT 1 FROM "user_suspension"
+            WHERE "user_id" = $2
+            AND ("suspended_until" IS NULL OR "suspended_until" > NOW())
+        )
+        "#,
+        user.session_id,
+        user.user_id
+    )
+    .fetch_one(&ctx.pool)
+    .await?
+    .count
+        == Some(1);
+
+    let ttl = user.expires_at - OffsetDateTime::now_utc().unix_timestamp();
+    if active && ttl > 0 {
+        // NX, so the session can not be made valid again if it was revoked in the meantime
+        let result: redis::RedisResult<()> = redis::cmd("SET")
+            .arg(&key)
+            .arg(VALID)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl)
+            .query_async(&mut ctx.redis.clone())
+            .await;
+        if let Err(e) = result {
+            log::error!("failed to cache session: {e:?}");
+        }
+    }
+
+    Ok(active)
+}
+
+/// Must be called after sessions are deleted, so their access tokens stop working
+pub async fn revoke_tokens(ctx: &HttpContext, session_ids: &[Uuid]) {
+    if session_ids.is_empty() {
+        return;
+    }
+
+    let mut pipe = redis::pipe();
+    for session_id in session_ids {
+        pipe.set_ex(cache_key(*session_id), REVOKED, ACCESS_LIFE_TIME.whole_seconds() as u64)
+            .ignore();
+    }
+    let result: redis::RedisResult<()> = pipe.query_async(&mut ctx.redis.clone()).await;
+
+    if let Err(e) = result {
+        log::error!("failed to revoke access tokens of sessions {session_ids:?}: {e:?}");
+    }
+}
+
+/// Remembers that the session was used, it is written to the database later
+pub fn touch(ctx: &HttpContext, session_id: Uuid) {
+    ctx.last_active
+        .lock()
+        .unwrap()
+        .insert(session_id, OffsetDateTime::now_utc());
+}
+
+async fn write_last_active(pool: &PgPool, last_active: HashMap<Uuid, OffsetDateTime>) -> HttpResult<()> {
+    let (ids, times): (Vec<Uuid>, Vec<OffsetDateTime>) = last_active.into_iter().unzip();
+
+    sqlx::query!(
+        r#"
+        UPDATE "user_session" s
+        SET "last_active" = a."last_active"
+        FROM UNNEST($1::UUID[], $2::TIMESTAMPTZ[]) AS a("id", "last_active")
+        WHERE s."id" = a."id"
+        AND s."last_active" < a."last_active"
+        "#,
+        &ids,
+        &times
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Writes time of the last activity of sessions every [LAST_ACTIVE_INTERVAL]
+pub fn spawn_last_active_writer(pool: PgPool) -> LastActive {
+    let last_active = LastActive::default();
+
+    let pending = last_active.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(LAST_ACTIVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            let batch = std::mem::take(&mut *pending.lock().unwrap());
+            if batch.is_empty() {
+                continue;
+            }
+            if let Err(e) = write_last_active(&pool, batch).await {
+                log::error!("failed to write last activity of sessions: {e:?}");
+            }
+        }
+    });
+
+    last_active
+}
+
+pub async fn list(ctx: &HttpContext, user: AuthUser) -> HttpResult<Vec<Session>> {
+    let sessions = sqlx::query_as!(
+        Session,
+        r#"
+        SELECT
+            "family_id" AS "id", "user_ip", "user_agent", "user_country", "user_city", "last_active",
+            "family_id" = (
+                SELECT "family_id" FROM "user_session"
+                WHERE "id" = $2
+            ) AS "current!"
+        FROM "user_session"
+        WHERE "user_id" = $1
+        ORDER BY "last_active" DESC
+        "#,
+        user.user_id,
+        user.session_id
+    )
+    .fetch_all(&ctx.pool)
+    .await?;
+
+    Ok(sessions)
+}
+
+/// Session is identified by its family id, which does not change on refresh.
+/// Revoking the current session is the same as logging out.
+pub async fn revoke(ctx: &HttpContext, user: AuthUser, family_id: Uuid) -> HttpResult<()> {
+    let revoked: Vec<Uuid> = sqlx::query!(
+        r#"
+        DELETE FROM "user_session"
+        WHERE "family_id" = $1
+        AND "user_id" = $2
+        RETURNING "id"
+        "#,
+        family_id,
+        user.user_id
+    )
+    .fetch_all(&ctx.pool)
+    .await?
+    .into_iter()
+    .map(|session| session.id)
+    .collect();
+
+    if revoked.is_empty() {
+        return Err(HttpError::not_found("Session not found"));
+    }
+    revoke_tokens(ctx, &revoked).await;
+    Ok(())
+}
+
+/// Logs out from every device except the current one
+pub async fn revoke_others(ctx: &HttpContext, user: &AuthUser) -> HttpResult<()> {
+    let revoked: Vec<Uuid> = sqlx::query!(
+        r#"
+        DELETE FROM "user_session"
+        WHERE "user_id" = $1
+        AND "family_id" != (
+            SELECT "family_id" FROM "user_session"
+            WHERE "id" = $2
+        )
+        RETURNING "id"
+        "#,
+        user.user_id,
+        user.session_id
+    )
+    .fetch_all(&ctx.pool)
+    .await?
+    .into_iter()
+    .map(|session| session.id)
+    .collect();
+
+    revoke_tokens(ctx, &revoked).await;
+    Ok(())
+}
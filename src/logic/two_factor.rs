@@ -0,0 +1,241 @@
+//! TOTP two-factor authentication.
+//! Enrollment returns a secret and recovery codes, but 2FA is only enabled
+//! after the user confirms it with a valid code from the app.
+//! Failed codes are counted for the account, no matter where they come from,
+//! because there are too few codes to let anyone guess them from many addresses.
+
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::{
+    http::{AuthUser, HttpError, HttpErrorContext, HttpResult, HttpContext},
+    logic::{auth, lockout},
+    models::http_models::{TwoFactorCodeBody, TwoFactorEnrollBody, TwoFactorEnrollment},
+    utils::{secret_token, totp},
+};
+
+const RECOVERY_CODES: usize = 10;
+
+/// Shown in the authenticator app next to the account
+static ISSUER: Lazy<String> = Lazy::new(|| std::env::var("DOMAIN").expect("DOMAIN env variable is not set"));
+
+/// Recovery codes look like `1a2b3-c4d5e`
+fn generate_recovery_code() -> String {
+    let mut bytes = [0; 5];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let code = hex::encode(bytes);
+    format!("{}-{}", &code[..5], &code[5..])
+}
+
+/// Dashes, spaces and case do not matter when entering recovery codes
+fn hash_recovery_code(code: &str) -> String {
+    let code: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    secret_token::hash(&code)
+}
+
+pub async fn is_enabled(pool: &PgPool, user_id: Uuid) -> HttpResult<bool> {
+    let enabled = sqlx::query!(
+        r#"
+        SELECT COUNT(1) FROM "user_totp"
+        WHERE "user_id" = $1
+        AND "enabled_at" IS NOT NULL
+        "#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await?
+    .count
+        == Some(1);
+
+    Ok(enabled)
+}
+
+/// Accepts the code from the app only once,
+/// so it can not be reused by someone who saw it.
+async fn use_totp(pool: &PgPool, user_id: Uuid, code: &str, enabled: bool) -> HttpResult<bool> {
+    let totp = sqlx::query!(
+        r#"
+        SELECT "secret" FROM "user_totp"
+        WHERE "user_id" = $1
+        AND ("enabled_at" IS NOT NULL) = $2
+        "#,
+        user_id,
+        enabled
+    )
+    .fetch_optional(pool)
+    .await?
+    .http_context(HttpError::bad_request("Two-factor authentication is not set up"))?;
+
+    let Some(step) = totp::verify(&totp.secret, code, OffsetDateTime::now_utc().unix_timestamp()) else {
+        return Ok(false);
+    };
+
+    let used = sqlx::query!(
+        r#"
+        UPDATE "user_totp"
+        SET
+            "last_used_step" = $2,
+            "enabled_at" = COALESCE("enabled_at", NOW())
+        WHERE "user_id" = $1
+        AND ("last_used_step" IS NULL OR "last_used_step" < $2)
+        "#,
+        user_id,
+        step
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(used == 1)
+}
+
+async fn use_recovery_code(pool: &PgPool, user_id: Uuid, code: &str) -> HttpResult<bool> {
+    let used = sqlx::query!(
+        r#"
+        DELETE FROM "user_recovery_code"
+        WHERE "user_id" = $1
+        AND "code_hash" = $2
+        "#,
+        user_id,
+        hash_recovery_code(code)
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(used == 1)
+}
+
+/// Checks TOTP or one of the recovery codes of the user with enabled 2FA
+pub async fn check_code(ctx: &HttpContext, user_id: Uuid, code: &str) -> HttpResult<()> {
+    let lockout_key = format!("mfa:{user_id}");
+    lockout::check(ctx, &lockout_key).await?;
+
+    if use_totp(&ctx.pool, user_id, code, true).await?
+        || use_recovery_code(&ctx.pool, user_id, code).await?
+    {
+        return lockout::reset(ctx, &lockout_key).await;
+    }
+
+    lockout::fail(ctx, &lockout_key).await?;
+    Err(HttpError::bad_request("Code is wrong"))
+}
+
+/// Starts the enrollment, previous unconfirmed one is replaced
+pub async fn enroll(
+    ctx: &HttpContext,
+    user: AuthUser,
+    body: TwoFactorEnrollBody,
+) -> HttpResult<TwoFactorEnrollment> {
+    if is_enabled(&ctx.pool, user.user_id).await? {
+        return Err(HttpError::bad_request("Two-factor authentication is already enabled"));
+    }
+    auth::reauthenticate(ctx, &user, body.password, "set up two-factor authentication").await?;
+
+    let username = sqlx::query!(
+        r#"
+        SELECT "username" FROM "user"
+        WHERE "id" = $1
+        "#,
+        user.user_id
+    )
+    .fetch_one(&ctx.pool)
+    .await?
+    .username;
+
+    let secret = totp::generate_secret();
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODES).map(|_| generate_recovery_code()).collect();
+    let code_hashes: Vec<String> = recovery_codes.iter().map(|code| hash_recovery_code(code)).collect();
+
+    let mut tx = ctx.pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO "user_totp" ("user_id", "secret")
+        VALUES ($1, $2)
+        ON CONFLICT ("user_id") DO UPDATE
+        SET
+            "secret" = EXCLUDED."secret",
+            "last_used_step" = NULL,
+            "created_at" = NOW()
+        "#,
+        user.user_id,
+        secret
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM "user_recovery_code"
+        WHERE "user_id" = $1
+        "#,
+        user.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO "user_recovery_code" ("code_hash", "user_id")
+        SELECT UNNEST($2::TEXT[]), $1
+        "#,
+        user.user_id,
+        &code_hashes
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(TwoFactorEnrollment {
+        uri: totp::uri(&secret, &ISSUER, &username),
+        secret: totp::encode_secret(&secret),
+        recovery_codes,
+    })
+}
+
+/// Enables 2FA if the code from the app is valid
+pub async fn confirm(ctx: &HttpContext, user: AuthUser, body: TwoFactorCodeBody) -> HttpResult<()> {
+    if !use_totp(&ctx.pool, user.user_id, &body.code, false).await? {
+        return Err(HttpError::bad_request("Code is wrong"));
+    }
+    Ok(())
+}
+
+pub async fn disable(ctx: &HttpContext, user: AuthUser, body: TwoFactorCodeBody) -> HttpResult<()> {
+    check_code(ctx, user.user_id, &body.code).await?;
+
+    let mut tx = ctx.pool.begin().await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM "user_totp"
+        WHERE "user_id" = $1
+        "#,
+        user.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM "user_recovery_code"
+        WHERE "user_id" = $1
+        "#,
+        user.user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
@@ -0,0 +1,384 @@
+use std::{io::SeekFrom, path::{Path, PathBuf}};
+use anyhow::Context;
+use axum::{
+    body::Body,
+    extract::{multipart::{Field, MultipartError}, Multipart},
+    http::{
+        header::{
+            ACCEPT_RANGES, CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE,
+            CONTENT_TYPE, ETAG, IF_NONE_MATCH, IF_RANGE, RANGE, X_CONTENT_TYPE_OPTIONS,
+        },
+        HeaderMap, HeaderName, StatusCode,
+    },
+    response::Response,
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+};
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+use crate::{
+    http::{HttpError, HttpErrorContext, HttpResult, HttpContext},
+    models::{database_models::Upload, http_models::UploadQuery},
+    utils::{
+        avatar,
+        http_range::{self, ByteRange},
+        storage::{self, CONTENT_TYPES, INLINE_CONTENT_TYPES, MAX_FILES, MAX_SIZE},
+    },
+};
+
+/// Folder for files uploaded directly by users
+const FOLDER: &str = "files";
+/// Folder for processed avatars of users and chats
+const AVATAR_FOLDER: &str = "avatars";
+
+fn too_large() -> HttpError {
+    HttpError::payload_too_large(format!("File must be at most {} bytes", *MAX_SIZE))
+}
+
+/// Body limit of the route is reported as a multipart error as well
+fn multipart_error(e: MultipartError) -> HttpError {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        return HttpError::payload_too_large(e.body_text());
+    }
+    HttpError::bad_request(e.body_text())
+}
+
+/// Writes multipart field into the file, chunk by chunk.
+/// Returns size of the written file.
+async fn write_field(mut field: Field<'_>, path: &Path) -> HttpResult<u64> {
+    let mut file = File::create(path)
+        .await
+        .context("failed to create uploaded file")?;
+    let mut size = 0;
+
+    while let Some(chunk) = field
+        .chunk()
+        .await
+        .map_err(multipart_error)?
+    {
+        size += chunk.len() as u64;
+        if size > *MAX_SIZE {
+            return Err(too_large());
+        }
+        file.write_all(&chunk)
+            .await
+            .context("failed to write uploaded file")?;
+    }
+
+    file.flush().await.context("failed to write uploaded file")?;
+    Ok(size)
+}
+
+async fn save_field(
+    ctx: &HttpContext,
+    field: Field<'_>,
+    file_name: String,
+    content_type: String,
+) -> HttpResult<Upload> {
+    let id = Uuid::new_v4();
+    let extension = storage::extension(&file_name);
+    let path = storage::file_path(FOLDER, id, &extension);
+
+    tokio::fs::create_dir_all(storage::folder_path(FOLDER))
+        .await
+        .context("failed to create uploads directory")?;
+
+    let size = match write_field(field, &path).await {
+        Ok(size) => size,
+        Err(e) => {
+            tokio::fs::remove_file(&path).await.ok();
+            return Err(e);
+        }
+    };
+
+    let upload = sqlx::query_as!(
+        Upload,
+        r#"
+        INSERT INTO "upload" (
+            "id",
+            "file_name",
+            "extension",
+            "content_type",
+            "folder",
+            "size"
+        ) VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING "id", "file_name", "extension", "content_type", "folder", "size", "created_at"
+        "#,
+        id,
+        file_name,
+        extension,
+        content_type,
+        FOLDER,
+        size as i64
+    )
+    .fetch_one(&ctx.pool)
+    .await;
+
+    if upload.is_err() {
+        tokio::fs::remove_file(&path).await.ok();
+    }
+    Ok(upload?)
+}
+
+/// Saves every file from the multipart body, at most [MAX_FILES] of them.
+/// Fields without a file name are ignored.
+pub async fn create(ctx: &HttpContext, mut multipart: Multipart) -> HttpResult<Vec<Upload>> {
+    let mut uploads = Vec::new();
+
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(multipart_error)?
+    {
+        let Some(file_name) = field.file_name().map(str::to_string) else {
+            continue;
+        };
+        if uploads.len() >= *MAX_FILES {
+            return Err(HttpError::bad_request(format!(
+                "At most {} files can be uploaded at once",
+                *MAX_FILES
+            )));
+        }
+        let content_type = field
+            .content_type()
+            .map(str::to_lowercase)
+            .unwrap_or_default();
+
+        if !CONTENT_TYPES.contains(&content_type) {
+            return Err(HttpError::bad_request(format!(
+                "Files of type `{content_type}` are not allowed"
+            )));
+        }
+
+        uploads.push(save_field(ctx, field, file_name, content_type).await?);
+    }
+
+    if uploads.is_empty() {
+        return Err(HttpError::bad_request("No files were uploaded"));
+    }
+    Ok(uploads)
+}
+
+/// Reads the first file from the multipart body into memory
+async fn read_file(mut multipart: Multipart) -> HttpResult<Vec<u8>> {
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(multipart_error)?
+    {
+        if field.file_name().is_none() {
+            continue;
+        }
+
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field
+            .chunk()
+            .await
+            .map_err(multipart_error)?
+        {
+            if (bytes.len() + chunk.len()) as u64 > *MAX_SIZE {
+                return Err(too_large());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+        return Ok(bytes);
+    }
+
+    Err(HttpError::bad_request("No file was uploaded"))
+}
+
+/// The biggest avatar is stored as the main file, others are its variants
+fn avatar_path(id: Uuid, size: u32) -> PathBuf {
+    if size == avatar::SIZES[avatar::SIZES.len() - 1] {
+        storage::file_path(AVATAR_FOLDER, id, ".webp")
+    } else {
+        storage::variant_path(AVATAR_FOLDER, id, &size.to_string(), ".webp")
+    }
+}
+
+async fn write_avatars(id: Uuid, images: &[avatar::AvatarImage]) -> HttpResult<()> {
+    tokio::fs::create_dir_all(storage::folder_path(AVATAR_FOLDER))
+        .await
+        .context("failed to create avatars directory")?;
+
+    for image in images {
+        tokio::fs::write(avatar_path(id, image.size), &image.data)
+            .await
+            .context("failed to write avatar")?;
+    }
+    Ok(())
+}
+
+async fn remove_avatars(id: Uuid) {
+    for size in avatar::SIZES {
+        tokio::fs::remove_file(avatar_path(id, size)).await.ok();
+    }
+}
+
+/// Converts the first file from the multipart body into avatars of different sizes.
+/// They are saved as one upload, specific size can be requested when it is served.
+/// Files are only kept if the upload is saved to the database.
+pub async fn create_avatar(ctx: &HttpContext, multipart: Multipart) -> HttpResult<Upload> {
+    let bytes = read_file(multipart).await?;
+    let images = avatar::process(bytes).await?;
+
+    let id = Uuid::new_v4();
+    // size of the largest one, which is served by default
+    let size = images.last().map(|image| image.data.len()).unwrap_or_default();
+
+    let mut tx = ctx.pool.begin().await?;
+
+    let upload = sqlx::query_as!(
+        Upload,
+        r#"
+        INSERT INTO "upload" (
+            "id",
+            "file_name",
+            "extension",
+            "content_type",
+            "folder",
+            "size"
+        ) VALUES ($1, 'avatar.webp', '.webp', 'image/webp', $2, $3)
+        RETURNING "id", "file_name", "extension", "content_type", "folder", "size", "created_at"
+        "#,
+        id,
+        AVATAR_FOLDER,
+        size as i64
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let saved = match write_avatars(id, &images).await {
+        Ok(()) => tx.commit().await.map_err(HttpError::from),
+        Err(e) => Err(e),
+    };
+    if let Err(e) = saved {
+        remove_avatars(id).await;
+        return Err(e);
+    }
+
+    Ok(upload)
+}
+
+pub async fn get_upload(ctx: &HttpContext, upload_id: Uuid) -> HttpResult<Upload> {
+    let upload = sqlx::query_as!(
+        Upload,
+        r#"
+        SELECT "id", "file_name", "extension", "content_type", "folder", "size", "created_at"
+        FROM "upload"
+        WHERE "id" = $1
+        "#,
+        upload_id
+    )
+    .fetch_optional(&ctx.pool)
+    .await?
+    .http_context(HttpError::not_found("Upload not found"))?;
+
+    Ok(upload)
+}
+
+/// Builds a response with the file, honoring `If-None-Match` and `Range` headers.
+/// Uploaded files never change, so their id (and size of the avatar) is used as an `ETag`.
+/// Size is ignored for anything other than avatars.
+pub async fn serve(
+    ctx: &HttpContext,
+    upload_id: Uuid,
+    query: UploadQuery,
+    headers: &HeaderMap,
+) -> HttpResult<Response> {
+    let upload = get_upload(ctx, upload_id).await?;
+
+    match query.size {
+        Some(size) if upload.folder == AVATAR_FOLDER => {
+            let size = avatar::closest_size(size);
+            let etag = format!("\"{}-{size}\"", upload.id);
+            serve_file(&upload, &avatar_path(upload.id, size), etag, headers).await
+        }
+        _ => {
+            let path = storage::file_path(&upload.folder, upload.id, &upload.extension);
+            let etag = format!("\"{}\"", upload.id);
+            serve_file(&upload, &path, etag, headers).await
+        }
+    }
+}
+
+async fn serve_file(
+    upload: &Upload,
+    path: &Path,
+    etag: String,
+    headers: &HeaderMap,
+) -> HttpResult<Response> {
+    let header = |name: HeaderName| headers.get(name).and_then(|value| value.to_str().ok());
+
+    if header(IF_NONE_MATCH).is_some_and(|value| value == etag || value == "*") {
+        let response = Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(ETAG, &etag)
+            .body(Body::empty())
+            .context("failed to build response")?;
+        return Ok(response);
+    }
+
+    let mut file = File::open(path)
+        .await
+        .with_context(|| format!("failed to open uploaded file {}", path.display()))?;
+    let size = file
+        .metadata()
+        .await
+        .context("failed to read uploaded file metadata")?
+        .len();
+
+    // Range is ignored if the client has an outdated version of the file
+    let range = match header(IF_RANGE) {
+        Some(value) if value != etag => ByteRange::Full,
+        _ => http_range::parse(header(RANGE), size),
+    };
+
+    let file_name: String = upload
+        .file_name
+        .chars()
+        .filter(|c| c.is_ascii_graphic() || *c == ' ')
+        .filter(|c| *c != '"' && *c != '\\')
+        .collect();
+
+    let disposition = match INLINE_CONTENT_TYPES.contains(&upload.content_type.as_str()) {
+        true => "inline",
+        false => "attachment",
+    };
+
+    let response = Response::builder()
+        .header(CONTENT_TYPE, &upload.content_type)
+        .header(X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .header(CONTENT_DISPOSITION, format!("{disposition}; filename=\"{file_name}\""))
+        .header(ETAG, &etag)
+        .header(ACCEPT_RANGES, "bytes")
+        .header(CACHE_CONTROL, "public, max-age=31536000, immutable");
+
+    let response = match range {
+        ByteRange::Full => response
+            .status(StatusCode::OK)
+            .header(CONTENT_LENGTH, size)
+            .body(Body::from_stream(ReaderStream::new(file))),
+        ByteRange::Partial(range) => {
+            let (start, end) = range.into_inner();
+            let length = end - start + 1;
+            file.seek(SeekFrom::Start(start))
+                .await
+                .context("failed to seek uploaded file")?;
+            response
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(CONTENT_LENGTH, length)
+                .header(CONTENT_RANGE, format!("bytes {start}-{end}/{size}"))
+                .body(Body::from_stream(ReaderStream::new(file.take(length))))
+        }
+        ByteRange::Unsatisfiable => response
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(CONTENT_RANGE, format!("bytes */{size}"))
+            .body(Body::empty()),
+    };
+
+    Ok(response.context("failed to build response")?)
+}
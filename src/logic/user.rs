@@ -0,0 +1,227 @@
+use axum::extract::Multipart;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    http::{AuthUser, MaybeAuthUser, HttpError, HttpErrorContext, HttpResult, HttpContext, ResultExt},
+    logic::{auth, block::is_blocked, presence, session, upload, verification},
+    models::{
+        database_models::{MyUser, User},
+        http_models::{EditUserBody, UserProfile},
+    },
+    utils::{password::hash_password, password_policy},
+};
+
+pub async fn get_me(ctx: &HttpContext, user: AuthUser) -> HttpResult<MyUser> {
+    let user = sqlx::query_as!(
+        MyUser,
+        r#"
+        SELECT "id", "username", "email", "display_name", "avatar", "status", "hide_last_seen",
+            "email_verified_at"
+        FROM "user"
+        WHERE "id" = $1
+        "#,
+        user.user_id
+    )
+    .fetch_one(&ctx.pool)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn edit_me(
+    ctx: &HttpContext,
+    user: AuthUser,
+    body: EditUserBody,
+) -> HttpResult<MyUser> {
+    // same as in registration, everything is stored in lowercase
+    let username = body.username.map(|username| username.to_lowercase());
+    let email = body.email.map(|email| email.to_lowercase());
+    let password_changed = body.password.is_some();
+    if email.is_some() || password_changed {
+        auth::reauthenticate(ctx, &user, body.current_password, "change e-mail or password").await?;
+    }
+
+    let password_hash = match body.password {
+        Some(password) => {
+            let current = sqlx::query!(
+                r#"
+                SELECT "username", "email" FROM "user"
+                WHERE "id" = $1
+                "#,
+                user.user_id
+            )
+            .fetch_one(&ctx.pool)
+            .await?;
+
+            password_policy::check(
+                &password,
+                username.as_deref().unwrap_or(&current.username),
+                email.as_deref().unwrap_or(&current.email),
+            )
+            .await?;
+            Some(hash_password(password).await?)
+        }
+        None => None,
+    };
+
+    let updated = sqlx::query_as!(
+        MyUser,
+        r#"
+        UPDATE "user"
+        SET
+            "username" = COALESCE($2, "username"),
+            "email" = COALESCE($3, "email"),
+            "email_verified_at" = CASE
+                WHEN $3::TEXT IS NULL OR $3 = "email" THEN "email_verified_at"
+                ELSE NULL
+            END,
+            "password_hash" = COALESCE($4, "password_hash"),
+            "display_name" = COALESCE($5, "display_name"),
+            "status" = COALESCE($6, "status"),
+            "hide_last_seen" = COALESCE($7, "hide_last_seen")
+        WHERE "id" = $1
+        RETURNING "id", "username", "email", "display_name", "avatar", "status", "hide_last_seen",
+            "email_verified_at"
+        "#,
+        user.user_id,
+        username,
+        email,
+        password_hash,
+        body.display_name,
+        body.status,
+        body.hide_last_seen
+    )
+    .fetch_one(&ctx.pool)
+    .await
+    .on_constraint("user_username_key", |_| {
+        HttpError::bad_request("Username is already taken")
+    })
+    .on_constraint("user_email_key", |_| {
+        HttpError::bad_request("Email is already taken")
+    })?;
+
+    // new e-mail has to be verified again
+    if email.is_some() && updated.email_verified_at.0.is_none() {
+        verification::send(updated.id, updated.email.clone());
+    }
+    // whoever knew the old password is logged out everywhere else
+    if password_changed {
+        session::revoke_others(ctx, &user).await?;
+    }
+
+    Ok(updated)
+}
+
+pub async fn set_avatar(
+    ctx: &HttpContext,
+    user: AuthUser,
+    multipart: Multipart,
+) -> HttpResult<MyUser> {
+    let avatar = upload::create_avatar(ctx, multipart).await?;
+
+    let user = sqlx::query_as!(
+        MyUser,
+        r#"
+        UPDATE "user"
+        SET "avatar" = $2
+        WHERE "id" = $1
+        RETURNING "id", "username", "email", "display_name", "avatar", "status", "hide_last_seen",
+            "email_verified_at"
+        "#,
+        user.user_id,
+        avatar.id
+    )
+    .fetch_one(&ctx.pool)
+    .await?;
+
+    Ok(user)
+}
+
+pub async fn remove_avatar(ctx: &HttpContext, user: AuthUser) -> HttpResult<MyUser> {
+    let user = sqlx::query_as!(
+        MyUser,
+        r#"
+        UPDATE "user"
+        SET "avatar" = NULL
+        WHERE "id" = $1
+        RETURNING "id", "username", "email", "display_name", "avatar", "status", "hide_last_seen",
+            "email_verified_at"
+        "#,
+        user.user_id
+    )
+    .fetch_one(&ctx.pool)
+    .await?;
+
+    Ok(user)
+}
+
+/// Finds id of the user, returns [HttpError::NotFound] if there is no such user
+pub async fn get_id(pool: &PgPool, username: &str) -> HttpResult<Uuid> {
+    let id = sqlx::query!(
+        r#"
+        SELECT "id" FROM "user"
+        WHERE "username" = $1
+        "#,
+        username.to_lowercase()
+    )
+    .fetch_optional(pool)
+    .await?
+    .http_context(HttpError::not_found("User not found"))?
+    .id;
+
+    Ok(id)
+}
+
+/// Users blocked by the profile owner only see the minimal profile:
+/// no avatar, status or stats.
+pub async fn get_by_username(
+    ctx: &HttpContext,
+    viewer: MaybeAuthUser,
+    username: String,
+) -> HttpResult<UserProfile> {
+    let username = username.to_lowercase();
+    let mut user = sqlx::query_as!(
+        User,
+        r#"
+        SELECT "id", "username", "display_name", "avatar", "status",
+            FALSE AS "online!", NULL::TIMESTAMPTZ AS "last_seen", "hide_last_seen"
+        FROM "user"
+        WHERE "username" = $1
+        "#,
+        username
+    )
+    .fetch_optional(&ctx.pool)
+    .await?
+    .http_context(HttpError::not_found("User not found"))?;
+
+    if let Some(viewer_id) = viewer.user_id() {
+        if is_blocked(&ctx.pool, user.id, viewer_id).await? {
+            user.avatar = None;
+            user.status = String::new();
+            return Ok(UserProfile {
+                user,
+                followers: 0,
+                following: 0,
+            });
+        }
+    }
+
+    let stats = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(1) FROM "user_subscribe_user" WHERE "to_user_id" = $1) AS "followers!",
+            (SELECT COUNT(1) FROM "user_subscribe_user" WHERE "from_user_id" = $1) AS "following!"
+        "#,
+        user.id
+    )
+    .fetch_one(&ctx.pool)
+    .await?;
+
+    presence::fill(ctx, std::slice::from_mut(&mut user)).await?;
+    Ok(UserProfile {
+        user,
+        followers: stats.followers,
+        following: stats.following,
+    })
+}
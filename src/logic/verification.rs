@@ -0,0 +1,75 @@
+//! E-mail verification.
+//! User receives a link with a signed token, which is valid for a day
+//! and only while the user has the same e-mail.
+
+use uuid::Uuid;
+
+use crate::{
+    http::{AuthUser, HttpError, HttpErrorContext, HttpResult, HttpContext},
+    models::http_models::VerifyEmailBody,
+    utils::{email, tokens::EmailClaims},
+};
+
+async fn try_send(user_id: Uuid, address: String) -> HttpResult<()> {
+    let token = EmailClaims::encode(user_id, address.clone())?;
+    let link = format!("{}/verify-email?token={token}", *email::APP_URL);
+
+    email::send(
+        &address,
+        "Verify your e-mail",
+        format!("Open this link to verify your e-mail:\n{link}\n\nIf it was not you, just ignore this e-mail."),
+    )
+    .await
+}
+
+/// Sends a verification link in the background.
+/// Failing to send it does not fail the request, user can ask for another one later.
+pub fn send(user_id: Uuid, address: String) {
+    tokio::spawn(async move {
+        if let Err(e) = try_send(user_id, address).await {
+            log::error!("failed to send verification e-mail: {e:?}");
+        }
+    });
+}
+
+pub async fn resend(ctx: &HttpContext, user: AuthUser) -> HttpResult<()> {
+    let row = sqlx::query!(
+        r#"
+        SELECT "email", "email_verified_at" FROM "user"
+        WHERE "id" = $1
+        "#,
+        user.user_id
+    )
+    .fetch_one(&ctx.pool)
+    .await?;
+
+    if row.email_verified_at.is_some() {
+        return Err(HttpError::bad_request("Email is already verified"));
+    }
+
+    try_send(user.user_id, row.email).await
+}
+
+pub async fn verify(ctx: &HttpContext, body: VerifyEmailBody) -> HttpResult<()> {
+    let invalid = || HttpError::bad_request("Verification link is invalid or expired");
+    let claims = EmailClaims::parse(&body.token).http_context(invalid())?;
+
+    let verified = sqlx::query!(
+        r#"
+        UPDATE "user"
+        SET "email_verified_at" = COALESCE("email_verified_at", NOW())
+        WHERE "id" = $1
+        AND "email" = $2
+        "#,
+        claims.user_id,
+        claims.email
+    )
+    .execute(&ctx.pool)
+    .await?
+    .rows_affected();
+
+    if verified == 0 {
+        return Err(invalid());
+    }
+    Ok(())
+}
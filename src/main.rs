@@ -6,5 +6,10 @@ use dotenvy::dotenv;
 async fn main() {
     dotenv().ok();
     env_logger::init();
-    webserver::run().await
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        ["rotate-keys"] => webserver::rotate_keys(),
+        ["grant-role", username, role] => webserver::grant_role(username, role).await,
+        _ => webserver::run().await
+    }
 }
\ No newline at end of file
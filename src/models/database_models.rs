@@ -6,4 +6,22 @@ mod user;
 pub use user::*;
 
 mod user_session;
-pub use user_session::*;
\ No newline at end of file
+pub use user_session::*;
+
+mod user_identity;
+pub use user_identity::*;
+
+mod role;
+pub use role::*;
+
+mod admin;
+pub use admin::*;
+
+mod chat;
+pub use chat::*;
+
+mod message;
+pub use message::*;
+
+mod upload;
+pub use upload::*;
\ No newline at end of file
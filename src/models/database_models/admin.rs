@@ -0,0 +1,31 @@
+use serde::Serialize;
+use uuid::Uuid;
+use crate::models::{Timestamptz, TimestamptzOption};
+
+/// User as seen by admins, with private info
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManagedUser {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub display_name: String,
+    pub email_verified_at: TimestamptzOption,
+    pub created_at: Timestamptz,
+    pub roles: Vec<String>,
+    pub suspended: bool,
+    /// Not set if suspension is permanent
+    pub suspended_until: TimestamptzOption,
+    pub suspension_reason: Option<String>
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub id: Uuid,
+    pub admin_id: Uuid,
+    pub action: String,
+    pub target_user_id: Option<Uuid>,
+    pub details: String,
+    pub created_at: Timestamptz
+}
@@ -0,0 +1,32 @@
+use serde::Serialize;
+use uuid::Uuid;
+use crate::models::Timestamptz;
+
+/// Chat type is stored as a text.\
+/// Can be `"group"`, `"private"` or `"saved"`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Chat {
+    pub id: Uuid,
+    #[serde(rename = "type")]
+    pub chat_type: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<Uuid>,
+    pub created_at: Timestamptz
+}
+
+/// [Chat] as it is shown in the list of user's chats
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatListItem {
+    pub id: Uuid,
+    #[serde(rename = "type")]
+    pub chat_type: String,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub image: Option<Uuid>,
+    pub created_at: Timestamptz,
+    /// Messages from other members that user has not read yet
+    pub unread_count: i64
+}
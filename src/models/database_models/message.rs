@@ -0,0 +1,84 @@
+use serde::Serialize;
+use uuid::Uuid;
+use crate::models::Timestamptz;
+
+/// Short version of a message,
+/// shown inside of messages that reply to it or forward it.
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MessagePreview {
+    pub id: Uuid,
+    pub sender_id: Uuid,
+    pub context: Option<String>
+}
+
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    pub id: Uuid,
+    pub chat_id: Uuid,
+    pub sender_id: Uuid,
+    pub context: Option<String>,
+    pub reply: Option<MessagePreview>,
+    pub forward: Option<MessagePreview>,
+    pub edited: bool,
+    pub created_at: Timestamptz,
+    pub updated_at: Timestamptz
+}
+
+/// Who and when has read a message
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageRead {
+    pub user_id: Uuid,
+    pub created_at: Timestamptz
+}
+
+/// Flat representation of a [Message] as it is selected from the database,
+/// replied and forwarded messages are joined to it.
+pub struct MessageRow {
+    pub id: Uuid,
+    pub chat_id: Uuid,
+    pub sender_id: Uuid,
+    pub context: Option<String>,
+    pub edited: bool,
+    pub created_at: Timestamptz,
+    pub updated_at: Timestamptz,
+    pub reply_id: Option<Uuid>,
+    pub reply_sender_id: Option<Uuid>,
+    pub reply_context: Option<String>,
+    pub forward_id: Option<Uuid>,
+    pub forward_sender_id: Option<Uuid>,
+    pub forward_context: Option<String>
+}
+
+impl From<MessageRow> for Message {
+    fn from(row: MessageRow) -> Self {
+        let reply = row.reply_id
+            .zip(row.reply_sender_id)
+            .map(|(id, sender_id)| MessagePreview {
+                id,
+                sender_id,
+                context: row.reply_context
+            });
+        let forward = row.forward_id
+            .zip(row.forward_sender_id)
+            .map(|(id, sender_id)| MessagePreview {
+                id,
+                sender_id,
+                context: row.forward_context
+            });
+
+        Self {
+            id: row.id,
+            chat_id: row.chat_id,
+            sender_id: row.sender_id,
+            context: row.context,
+            reply,
+            forward,
+            edited: row.edited,
+            created_at: row.created_at,
+            updated_at: row.updated_at
+        }
+    }
+}
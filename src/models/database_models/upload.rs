@@ -0,0 +1,17 @@
+use serde::Serialize;
+use uuid::Uuid;
+use crate::models::Timestamptz;
+
+/// Uploaded file.\
+/// File itself is stored on disk, check [storage][crate::utils::storage] module.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Upload {
+    pub id: Uuid,
+    pub file_name: String,
+    pub extension: String,
+    pub content_type: String,
+    pub folder: String,
+    pub size: i64,
+    pub created_at: Timestamptz
+}
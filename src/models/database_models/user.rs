@@ -10,7 +10,12 @@ pub struct User {
     pub display_name: String,
     pub avatar: Option<Uuid>,
     pub status: String,
-    pub online: TimestamptzOption
+    /// User has an open WebSocket connection
+    pub online: bool,
+    /// Hidden if user does not want to show it
+    pub last_seen: TimestamptzOption,
+    #[serde(skip)]
+    pub hide_last_seen: bool
 }
 
 #[derive(Serialize)]
@@ -21,5 +26,7 @@ pub struct MyUser {
     pub email: String,
     pub display_name: String,
     pub avatar: Option<Uuid>,
-    pub status: String
+    pub status: String,
+    pub hide_last_seen: bool,
+    pub email_verified_at: TimestamptzOption
 }
\ No newline at end of file
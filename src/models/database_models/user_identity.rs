@@ -0,0 +1,11 @@
+use serde::Serialize;
+use crate::models::Timestamptz;
+
+/// Account of external identity provider linked to the user
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserIdentity {
+    pub provider: String,
+    pub email: Option<String>,
+    pub created_at: Timestamptz
+}
@@ -5,11 +5,13 @@ use crate::models::Timestamptz;
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Session {
+    /// Family id of the session, it stays the same when tokens are refreshed
     pub id: Uuid,
-    pub user_id: Uuid,
     pub user_ip: String,
     pub user_agent: String,
     pub user_country: String,
     pub user_city: String,
-    pub last_active: Timestamptz
+    pub last_active: Timestamptz,
+    /// Session of the token this request was made with
+    pub current: bool
 }
\ No newline at end of file
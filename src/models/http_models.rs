@@ -9,4 +9,19 @@ mod auth;
 pub use auth::*;
 
 mod user;
-pub use user::*;
\ No newline at end of file
+pub use user::*;
+
+mod admin;
+pub use admin::*;
+
+mod chat;
+pub use chat::*;
+
+mod message;
+pub use message::*;
+
+mod upload;
+pub use upload::*;
+
+mod event;
+pub use event::*;
\ No newline at end of file
@@ -0,0 +1,41 @@
+use serde::Deserialize;
+use uuid::Uuid;
+use validator::Validate;
+use crate::models::Timestamptz;
+
+/// Users are returned from the most recently registered.
+/// To get the next page, pass id of the last received user as `before`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSearchQuery {
+    /// Part of username, e-mail or display name
+    pub query: Option<String>,
+    pub before: Option<Uuid>,
+    pub limit: Option<i64>
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct SuspendBody {
+    #[validate(
+        length(
+            min = 1,
+            max = 512,
+            message = "Reason must be between 1 and 512 characters"
+        )
+    )]
+    pub reason: String,
+    /// Suspension is permanent if not set
+    pub until: Option<Timestamptz>
+}
+
+/// Entries are returned from the newest.
+/// To get the next page, pass id of the last received entry as `before`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditQuery {
+    /// Only actions on this user
+    pub user_id: Option<Uuid>,
+    pub before: Option<Uuid>,
+    pub limit: Option<i64>
+}
@@ -4,7 +4,7 @@ use regex::Regex;
 use once_cell::sync::Lazy;
 use crate::{models::database_models::User, utils::tokens::TokenPair};
 
-static USERNAME_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\w+$").unwrap());
+pub(super) static USERNAME_REGEX: Lazy<Regex> = Lazy::new(|| Regex::new(r"^\w+$").unwrap());
 
 #[derive(Deserialize, Validate)]
 #[serde(rename_all = "camelCase")]
@@ -27,19 +27,15 @@ pub struct RegisterBody {
         )
     )]
     pub email: String,
-    #[validate(
-        length(
-            min = 3,
-            message = "Password must be at least 3 characters"
-        )
-    )]
+    /// Checked by [password_policy][crate::utils::password_policy]
     pub password: String
 }
 
 #[derive(Deserialize, Validate)]
 #[serde(rename_all = "camelCase")]
 pub struct LoginBody {
-    pub username: String,
+    /// Username or email, in any case
+    pub login: String,
     pub password: String
 }
 
@@ -49,9 +45,93 @@ pub struct RefreshBody {
     pub refresh_token: String
 }
 
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct VerifyEmailBody {
+    pub token: String
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ForgotPasswordBody {
+    #[validate(
+        email(
+            message = "Email must be valid"
+        )
+    )]
+    pub email: String
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct ResetPasswordBody {
+    pub token: String,
+    /// Checked by [password_policy][crate::utils::password_policy]
+    pub password: String
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct LoginMfaBody {
+    pub ticket: String,
+    /// TOTP or one of the recovery codes
+    pub code: String
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct TwoFactorEnrollBody {
+    /// Current password, users without one must have logged in recently
+    pub password: Option<String>
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct TwoFactorCodeBody {
+    /// TOTP or one of the recovery codes
+    pub code: String
+}
+
+/// Parameters which identity provider sent to the redirect url
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct OidcCallbackBody {
+    pub code: String,
+    pub state: String
+}
+
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AuthResponse {
     pub user: User,
     pub tokens: TokenPair
-}
\ No newline at end of file
+}
+
+/// Users with two-factor authentication get a ticket instead of tokens,
+/// it has to be exchanged with a valid code
+#[derive(Serialize)]
+#[serde(untagged)]
+pub enum LoginResponse {
+    Authenticated(AuthResponse),
+    #[serde(rename_all = "camelCase")]
+    MfaRequired {
+        mfa_ticket: String
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TwoFactorEnrollment {
+    /// Usually shown as a QR code
+    pub uri: String,
+    /// For entering into the app manually
+    pub secret: String,
+    /// Shown only once, each can be used instead of TOTP once
+    pub recovery_codes: Vec<String>
+}
+/// Login page of identity provider, user has to be sent there
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorizationUrl {
+    pub url: String
+}
@@ -0,0 +1,52 @@
+use serde::Deserialize;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateGroupChatBody {
+    #[validate(
+        length(
+            min = 1,
+            max = 64,
+            message = "Chat name must be between 1 and 64 characters"
+        )
+    )]
+    pub name: String,
+    #[validate(
+        length(
+            max = 256,
+            message = "Chat description must be at most 256 characters"
+        )
+    )]
+    pub description: Option<String>,
+    /// Users to add to the chat, creator is added automatically
+    #[serde(default)]
+    pub members: Vec<Uuid>
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenPrivateChatBody {
+    pub user_id: Uuid
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct EditChatBody {
+    #[validate(
+        length(
+            min = 1,
+            max = 64,
+            message = "Chat name must be between 1 and 64 characters"
+        )
+    )]
+    pub name: Option<String>,
+    #[validate(
+        length(
+            max = 256,
+            message = "Chat description must be at most 256 characters"
+        )
+    )]
+    pub description: Option<String>
+}
@@ -0,0 +1,53 @@
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::models::database_models::Message;
+
+/// Events pushed to users through the WebSocket gateway
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Event {
+    MessageCreated {
+        message: Message
+    },
+    MessageEdited {
+        message: Message
+    },
+    #[serde(rename_all = "camelCase")]
+    MessageDeleted {
+        chat_id: Uuid,
+        message_id: Uuid
+    },
+    /// User has read messages up to this one
+    #[serde(rename_all = "camelCase")]
+    MessageRead {
+        chat_id: Uuid,
+        user_id: Uuid,
+        message_id: Uuid
+    },
+    #[serde(rename_all = "camelCase")]
+    Typing {
+        chat_id: Uuid,
+        user_id: Uuid
+    },
+    #[serde(rename_all = "camelCase")]
+    Presence {
+        user_id: Uuid,
+        online: bool
+    }
+}
+
+/// Events sent by users through the WebSocket gateway
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ClientEvent {
+    /// Must be the first event if access token was not in the `Authorization` header,
+    /// browsers can not set headers for WebSocket connections
+    Authenticate {
+        token: String
+    },
+    #[serde(rename_all = "camelCase")]
+    Typing {
+        chat_id: Uuid
+    }
+}
+
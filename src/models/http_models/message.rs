@@ -0,0 +1,40 @@
+use serde::Deserialize;
+use uuid::Uuid;
+use validator::Validate;
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct SendMessageBody {
+    #[validate(
+        length(
+            min = 1,
+            max = 4096,
+            message = "Message must be between 1 and 4096 characters"
+        )
+    )]
+    pub context: Option<String>,
+    pub reply_message_id: Option<Uuid>,
+    pub forward_message_id: Option<Uuid>
+}
+
+#[derive(Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct EditMessageBody {
+    #[validate(
+        length(
+            min = 1,
+            max = 4096,
+            message = "Message must be between 1 and 4096 characters"
+        )
+    )]
+    pub context: String
+}
+
+/// Messages are returned from newest to oldest.
+/// To get the next page, pass id of the last received message as `before`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MessagesQuery {
+    pub before: Option<Uuid>,
+    pub limit: Option<i64>
+}
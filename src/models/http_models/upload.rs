@@ -0,0 +1,8 @@
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UploadQuery {
+    /// Size of the avatar in pixels, closest stored size is returned
+    pub size: Option<u32>
+}
@@ -1,11 +1,67 @@
-use serde::Deserialize;
+use serde::{Serialize, Deserialize};
+use uuid::Uuid;
+use validator::Validate;
+use crate::models::database_models::User;
+use super::auth::USERNAME_REGEX;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Validate)]
 #[serde(rename_all = "camelCase")]
 pub struct EditUserBody {
+    #[validate(
+        length(
+            min = 3,
+            max = 24,
+            message = "Username must be between 3 and 24 characters"
+        ),
+        regex(
+            path = "USERNAME_REGEX",
+            message = "Username must only contain english letters, numbers and unserscore"
+        )
+    )]
     pub username: Option<String>,
+    #[validate(
+        email(
+            message = "Email must be valid"
+        )
+    )]
     pub email: Option<String>,
+    /// Checked by [password_policy][crate::utils::password_policy]
     pub password: Option<String>,
+    /// Required to change e-mail or password, unless the user has no password
+    pub current_password: Option<String>,
+    #[validate(
+        length(
+            min = 1,
+            max = 64,
+            message = "Display name must be between 1 and 64 characters"
+        )
+    )]
     pub display_name: Option<String>,
-    pub status: Option<String>
-}
\ No newline at end of file
+    #[validate(
+        length(
+            max = 128,
+            message = "Status must be at most 128 characters"
+        )
+    )]
+    pub status: Option<String>,
+    pub hide_last_seen: Option<bool>
+}
+
+/// Public user info with some stats
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserProfile {
+    #[serde(flatten)]
+    pub user: User,
+    pub followers: i64,
+    pub following: i64
+}
+
+/// Users are returned from the most recently followed.
+/// To get the next page, pass id of the last received user as `before`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FollowsQuery {
+    pub before: Option<Uuid>,
+    pub limit: Option<i64>
+}
@@ -11,12 +11,12 @@ use std::fmt::Formatter;
 /// not human-readable.
 ///
 /// With this wrapper type, we override this to provide the serialization format we want.
-#[derive(sqlx::Type)]
+#[derive(sqlx::Type, Clone, Copy)]
 pub struct Timestamptz(pub OffsetDateTime);
 
 /// This has to be used instead of `Option<Timestamptz>`\
 /// Because `From<Option<OffsetDateTime>> for Option<Timestamptz>` can not be implemented
-#[derive(sqlx::Type)]
+#[derive(sqlx::Type, Clone, Copy)]
 pub struct TimestamptzOption(pub Option<Timestamptz>);
 
 impl From<OffsetDateTime> for Timestamptz {
@@ -7,4 +7,11 @@ pub mod keys;
 pub mod tokens;
 pub mod user_agent;
 pub mod ip_info;
-pub mod email;
\ No newline at end of file
+pub mod email;
+pub mod storage;
+pub mod http_range;
+pub mod avatar;
+pub mod secret_token;
+pub mod totp;
+pub mod password_policy;
+pub mod oidc;
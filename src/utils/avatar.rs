@@ -0,0 +1,106 @@
+//! # Avatar images processing
+//! Uploaded images are decoded, center-cropped to a square
+//! and re-encoded to WebP in several sizes.
+//! Re-encoding only keeps the pixels, so all metadata (EXIF, GPS, etc.) is dropped.
+//! It is computationally intensive, so it is done inside a blocking thread.
+
+use std::io::Cursor;
+use anyhow::Context;
+use image::{
+    codecs::webp::WebPEncoder,
+    imageops::FilterType,
+    io::{Limits, Reader},
+    DynamicImage, ExtendedColorType,
+};
+use crate::http::{HttpError, HttpResult};
+
+/// Every avatar is stored in these sizes (in pixels).
+/// The last one is the biggest and is used by default.
+pub const SIZES: [u32; 3] = [64, 256, 512];
+
+/// Images bigger than that are not even decoded
+const MAX_DIMENSION: u32 = 8192;
+
+/// Resized WebP image
+pub struct AvatarImage {
+    pub size: u32,
+    pub data: Vec<u8>,
+}
+
+/// Picks the smallest stored size that is not less than requested one
+pub fn closest_size(requested: u32) -> u32 {
+    SIZES
+        .into_iter()
+        .find(|&size| size >= requested)
+        .unwrap_or(SIZES[SIZES.len() - 1])
+}
+
+fn decode(bytes: &[u8]) -> Option<DynamicImage> {
+    let mut limits = Limits::default();
+    limits.max_image_width = Some(MAX_DIMENSION);
+    limits.max_image_height = Some(MAX_DIMENSION);
+
+    let mut reader = Reader::new(Cursor::new(bytes)).with_guessed_format().ok()?;
+    reader.limits(limits);
+    reader.decode().ok()
+}
+
+fn encode(image: &DynamicImage) -> anyhow::Result<Vec<u8>> {
+    let image = image.to_rgba8();
+    let mut data = Vec::new();
+    WebPEncoder::new_lossless(&mut data)
+        .encode(&image, image.width(), image.height(), ExtendedColorType::Rgba8)
+        .context("failed to encode avatar")?;
+    Ok(data)
+}
+
+/// Converts uploaded image into avatars of all [SIZES].
+/// Returns `422 Unprocessable Entity` if the file is not an image.
+pub async fn process(bytes: Vec<u8>) -> HttpResult<Vec<AvatarImage>> {
+    tokio::task::spawn_blocking(move || -> HttpResult<Vec<AvatarImage>> {
+        let image = decode(&bytes).ok_or(HttpError::unprocessable_entity([(
+            "file",
+            "File must be a valid image",
+        )]))?;
+
+        let side = image.width().min(image.height());
+        let square = image.crop_imm(
+            (image.width() - side) / 2,
+            (image.height() - side) / 2,
+            side,
+            side,
+        );
+
+        SIZES
+            .into_iter()
+            .map(|size| {
+                let resized = square.resize_exact(size, size, FilterType::Lanczos3);
+                Ok(AvatarImage { size, data: encode(&resized)? })
+            })
+            .collect()
+    })
+    .await
+    .context("failed to process avatar")?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, RgbImage};
+
+    #[tokio::test]
+    async fn test_avatar() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgb8(RgbImage::new(300, 200))
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .expect("failed to encode test image");
+
+        let avatars = process(png).await.expect("failed to process avatar");
+        for (avatar, size) in avatars.iter().zip(SIZES) {
+            let image = image::load_from_memory(&avatar.data).expect("failed to decode avatar");
+            assert_eq!((image.width(), image.height()), (size, size));
+        }
+
+        assert!(process(b"not an image".to_vec()).await.is_err());
+    }
+}
@@ -1,22 +1,95 @@
-//! # Sending e-mails using SMTP
+//! # Sending e-mails
 //! Can be used to send verification codes, password reset links, etc.
+//!
+//! The way e-mails are delivered is set by `MAIL_TRANSPORT` env variable:
+//! - `smtp` (default) - sent using SMTP server from `SMTP_HOST` (`smtp.gmail.com` by default)
+//!   with `SMTP_ADDRESS` and `SMTP_PASSWORD` credentials
+//! - `file` - saved as `.eml` files into `MAIL_DIR` (`data/mail` by default)
+//! - `stdout` - printed to the standard output
+//!
+//! Last two are meant for local development and tests.
 
-// WIP
-
-// use anyhow::Context;
-// use lettre::{message::header::ContentType, transport::smtp::authentication::Credentials, Message, SmtpTransport, Transport};
-// use uuid::Uuid;
-// use once_cell::sync::Lazy;
-// use crate::http::HttpResult;
-
-// static DOMAIN: Lazy<String> = Lazy::new(|| std::env::var("DOMAIN").expect("DOMAIN env variable is not set"));
-// static SMTP_ADDRESS: Lazy<String> = Lazy::new(|| std::env::var("SMTP_ADDRESS").expect("SMTP_ADDRESS env variable is not set"));
-// static SMTP_PASSWORD: Lazy<String> = Lazy::new(|| std::env::var("SMTP_PASSWORD").expect("SMTP_PASSWORD env variable is not set"));
-// static MAILER: Lazy<SmtpTransport> = Lazy::new(|| {
-//     let creds = Credentials::new(SMTP_ADDRESS.to_string(), SMTP_PASSWORD.to_string());
-//     SmtpTransport::relay("smtp.gmail.com")
-//         .unwrap()
-//         .credentials(creds)
-//         .build()
-// });
-// static FROM: Lazy<String> = Lazy::new(|| format!("{} <{}>", DOMAIN.to_string(), SMTP_ADDRESS.to_string()));
\ No newline at end of file
+use anyhow::Context;
+use lettre::{
+    message::{header::ContentType, Mailbox},
+    transport::smtp::authentication::Credentials,
+    AsyncFileTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use once_cell::sync::Lazy;
+use crate::http::HttpResult;
+
+const DEFAULT_SMTP_HOST: &str = "smtp.gmail.com";
+const DEFAULT_MAIL_DIR: &str = "data/mail";
+
+enum Mailer {
+    Smtp(AsyncSmtpTransport<Tokio1Executor>),
+    File(AsyncFileTransport<Tokio1Executor>),
+    Stdout,
+}
+
+static DOMAIN: Lazy<String> = Lazy::new(|| std::env::var("DOMAIN").expect("DOMAIN env variable is not set"));
+static SMTP_ADDRESS: Lazy<String> = Lazy::new(|| std::env::var("SMTP_ADDRESS").expect("SMTP_ADDRESS env variable is not set"));
+static SMTP_PASSWORD: Lazy<String> = Lazy::new(|| std::env::var("SMTP_PASSWORD").expect("SMTP_PASSWORD env variable is not set"));
+static MAIL_DIR: Lazy<String> = Lazy::new(|| std::env::var("MAIL_DIR").unwrap_or(DEFAULT_MAIL_DIR.to_string()));
+static MAILER: Lazy<Mailer> = Lazy::new(|| {
+    let transport = std::env::var("MAIL_TRANSPORT").unwrap_or("smtp".to_string());
+    match transport.as_str() {
+        "smtp" => {
+            let host = std::env::var("SMTP_HOST").unwrap_or(DEFAULT_SMTP_HOST.to_string());
+            let creds = Credentials::new(SMTP_ADDRESS.to_string(), SMTP_PASSWORD.to_string());
+            Mailer::Smtp(
+                AsyncSmtpTransport::<Tokio1Executor>::relay(&host)
+                    .expect("failed to create SMTP transport")
+                    .credentials(creds)
+                    .build()
+            )
+        }
+        "file" => Mailer::File(AsyncFileTransport::new(MAIL_DIR.as_str())),
+        "stdout" => Mailer::Stdout,
+        _ => panic!("MAIL_TRANSPORT env variable must be one of: smtp, file, stdout"),
+    }
+});
+static FROM: Lazy<Mailbox> = Lazy::new(|| {
+    let address = match *MAILER {
+        Mailer::Smtp(_) => SMTP_ADDRESS.to_string(),
+        _ => format!("noreply@{}", *DOMAIN),
+    };
+    format!("{} <{address}>", *DOMAIN)
+        .parse()
+        .expect("failed to parse sender address")
+});
+
+/// Public address of the app, used in links inside of e-mails.\
+/// `APP_URL` env variable, `https://DOMAIN` by default
+pub static APP_URL: Lazy<String> = Lazy::new(|| {
+    std::env::var("APP_URL").unwrap_or(format!("https://{}", *DOMAIN))
+});
+
+/// Sends a plain text e-mail
+pub async fn send(to: &str, subject: &str, body: String) -> HttpResult<()> {
+    let message = Message::builder()
+        .from(FROM.clone())
+        .to(to.parse().context("failed to parse recipient address")?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(body)
+        .context("failed to build e-mail")?;
+
+    match &*MAILER {
+        Mailer::Smtp(transport) => {
+            transport.send(message).await.context("failed to send e-mail")?;
+        }
+        Mailer::File(transport) => {
+            tokio::fs::create_dir_all(MAIL_DIR.as_str())
+                .await
+                .context("failed to create mail directory")?;
+            transport.send(message).await.context("failed to save e-mail")?;
+        }
+        Mailer::Stdout => {
+            println!("{}", String::from_utf8_lossy(&message.formatted()));
+        }
+    }
+
+    log::debug!("sent e-mail\nto: {to}\nsubject: {subject}");
+    Ok(())
+}
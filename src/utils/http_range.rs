@@ -0,0 +1,64 @@
+//! # Parsing of the HTTP `Range` header
+//! Only single byte ranges are supported, which is enough for media players and download managers.
+//! Requests with multiple ranges are answered with the whole file.
+
+use std::ops::RangeInclusive;
+
+/// Result of matching the `Range` header against the file size
+#[derive(Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// Header is missing or not supported, send the whole file
+    Full,
+    /// Send only this part of the file
+    Partial(RangeInclusive<u64>),
+    /// Range is outside of the file, respond with `416 Range Not Satisfiable`
+    Unsatisfiable,
+}
+
+/// Parses header value like `bytes=0-499`, `bytes=500-` or `bytes=-500`
+pub fn parse(header: Option<&str>, size: u64) -> ByteRange {
+    let Some(ranges) = header.and_then(|header| header.trim().strip_prefix("bytes=")) else {
+        return ByteRange::Full;
+    };
+    if ranges.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = ranges.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        // bytes=0-499
+        (Ok(start), Ok(end)) if start <= end => start..=end.min(size.saturating_sub(1)),
+        // bytes=500-
+        (Ok(start), Err(_)) if end.is_empty() => start..=size.saturating_sub(1),
+        // bytes=-500
+        (Err(_), Ok(suffix)) if start.is_empty() && suffix > 0 => {
+            size.saturating_sub(suffix)..=size.saturating_sub(1)
+        }
+        _ => return ByteRange::Full,
+    };
+
+    if size == 0 || *range.start() >= size {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range() {
+        assert_eq!(parse(None, 1000), ByteRange::Full);
+        assert_eq!(parse(Some("bytes=0-499"), 1000), ByteRange::Partial(0..=499));
+        assert_eq!(parse(Some("bytes=500-"), 1000), ByteRange::Partial(500..=999));
+        assert_eq!(parse(Some("bytes=-100"), 1000), ByteRange::Partial(900..=999));
+        assert_eq!(parse(Some("bytes=900-5000"), 1000), ByteRange::Partial(900..=999));
+        assert_eq!(parse(Some("bytes=1000-"), 1000), ByteRange::Unsatisfiable);
+        assert_eq!(parse(Some("bytes=0-1,5-6"), 1000), ByteRange::Full);
+        assert_eq!(parse(Some("bytes=5-1"), 1000), ByteRange::Full);
+        assert_eq!(parse(Some("items=0-1"), 1000), ByteRange::Full);
+    }
+}
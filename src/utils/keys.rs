@@ -1,77 +1,567 @@
-//! # RSA keys loading and generation
-//! Reading or generating RSA key pair for encryption and decryption.
-//! Keys are saved in `keys` directory in the root of the project.
-//! In case they can not be found, new key pair is generated.
-//! These keys are then only passed to [Tokens][super::tokens] module.
+//! # Signing keys loading, generation and rotation
+//! Reading or generating keys for signing and verifying tokens.
+//! Keys are saved in `keys` directory in the root of the project,
+//! every key has an id (`kid`), which is the unix timestamp of its creation:
+//! - `{kid}.private.key` and `{kid}.public.key` - the active key, new tokens are signed with it
+//! - `{kid}.public.key` only - retired keys, they are only used to verify tokens signed before rotation
+//!
+//! Algorithm of new keys is set by `TOKEN_ALGORITHM` env variable:
+//! `RS256` (2048-bit RSA, default), `ES256` (P-256) or `EdDSA` (Ed25519).
+//! Algorithm of every key is read from its public key, so after the variable is changed
+//! and keys are rotated, tokens signed with the old algorithm stay valid until they expire.
+//! Keys are never rotated on start, several servers starting at once would all do it.
+//!
+//! In case no keys can be found, new key is generated.
+//! Keys are rotated with `rotate-keys` command, they are reloaded from disk every minute,
+//! so all running servers start using the new key. Tokens signed with a key that is not loaded yet
+//! make the server reload keys right away, see [for_kid].
+//! Retired keys are removed on rotation, once every token signed with them has expired.
+//! These keys are then only passed to [Tokens][super::tokens] module
+//! and published as [JWKS][jwks] for other services.
 
-use std::path::PathBuf;
+use std::{path::{Path, PathBuf}, str::FromStr, sync::{Arc, Mutex, RwLock}, time::{Duration, Instant}};
 use anyhow::Context;
+use data_encoding::BASE64URL_NOPAD;
+use jsonwebtoken::{
+    Algorithm,
+    DecodingKey,
+    EncodingKey,
+    jwk::{
+        AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+        EllipticCurveKeyType, Jwk, JwkSet, KeyAlgorithm, OctetKeyPairParameters, OctetKeyPairType,
+        PublicKeyUse, RSAKeyParameters, RSAKeyType
+    }
+};
+use ring::{
+    rand::SystemRandom,
+    signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING}
+};
 use rsa::{
     RsaPrivateKey,
     RsaPublicKey,
+    traits::PublicKeyParts,
     pkcs8::{
         EncodePrivateKey,
         EncodePublicKey,
-        DecodePrivateKey,
         DecodePublicKey,
-        LineEnding
+        Document,
+        LineEnding,
+        ObjectIdentifier,
+        SecretDocument,
+        SubjectPublicKeyInfoRef,
+        der::asn1::{AnyRef, BitStringRef},
+        spki::AlgorithmIdentifierRef
     }
 };
 use once_cell::sync::Lazy;
+use time::OffsetDateTime;
+use super::tokens::REFRESH_LIFE_TIME;
+
+const KEYS_DIR: &str = "keys";
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+/// Unknown key ids do not reload keys more often, so made up tokens can not make every request read the disk
+const FORCED_RELOAD_INTERVAL: Duration = Duration::from_secs(5);
+/// Id given to the key pair from before rotation was supported (`private.key` and `public.key`).
+/// Tokens without `kid` were signed with it.
+const LEGACY_KID: &str = "0";
 
-pub(super) static KEY_PAIR: Lazy<RsaKeyPair> = Lazy::new(|| RsaKeyPair::get("keys").unwrap());
+const RSA_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const ED25519_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+const EC_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+const P256_OID: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7");
 
-#[derive(Clone)]
-pub struct RsaKeyPair {
-    pub private: RsaPrivateKey,
-    pub public: RsaPublicKey
+static ALGORITHM: Lazy<Algorithm> = Lazy::new(|| {
+    let algorithm = std::env::var("TOKEN_ALGORITHM").unwrap_or("RS256".to_string());
+    match Algorithm::from_str(&algorithm) {
+        Ok(algorithm @ (Algorithm::RS256 | Algorithm::ES256 | Algorithm::EdDSA)) => algorithm,
+        _ => panic!("TOKEN_ALGORITHM env variable must be one of: RS256, ES256, EdDSA"),
+    }
+});
+
+static KEYS: Lazy<RwLock<Arc<KeySet>>> = Lazy::new(|| {
+    let keys = init().unwrap_or_else(|e| panic!("failed to load signing keys: {e:?}"));
+    RwLock::new(Arc::new(keys))
+});
+static LAST_FORCED_RELOAD: Lazy<Mutex<Option<Instant>>> = Lazy::new(|| Mutex::new(None));
+
+pub struct Key {
+    pub kid: String,
+    pub algorithm: Algorithm,
+    jwk: Jwk,
+    /// Only the active key has it
+    pub encoding: Option<EncodingKey>,
+    pub decoding: DecodingKey,
 }
 
-impl RsaKeyPair {
-    fn get(dir_path: &str) -> anyhow::Result<Self> {
-        let current_dir: PathBuf = std::env::current_dir()
-            .context("failed to access current directory")?;
-        let keys_dir: PathBuf = current_dir.join(dir_path);
+/// All keys, ordered from the oldest to the newest
+pub struct KeySet {
+    keys: Vec<Key>,
+}
 
-        if !keys_dir
-        .try_exists()
-        .context("failed to check if keys directory exists")? {
-            log::warn!("Keys directory not found, creating new directory");
-            std::fs::create_dir(&keys_dir)
-            .context("failed to create keys directory")?;
+fn keys_dir() -> PathBuf {
+    std::env::current_dir()
+        .expect("failed to access current directory")
+        .join(KEYS_DIR)
+}
+
+fn private_key_file(dir: &Path, kid: &str) -> PathBuf {
+    dir.join(format!("{kid}.private.key"))
+}
+
+fn public_key_file(dir: &Path, kid: &str) -> PathBuf {
+    dir.join(format!("{kid}.public.key"))
+}
+
+/// Current keys, they may change after every reload
+pub fn current() -> Arc<KeySet> {
+    KEYS.read().unwrap().clone()
+}
+
+/// Algorithm and JWK of the public key
+fn read_public_key(file: &Path) -> anyhow::Result<(Algorithm, Jwk)> {
+    let (_, document) = Document::read_pem_file(file)
+        .context("failed to read public key")?;
+    let info = SubjectPublicKeyInfoRef::try_from(document.as_bytes())
+        .context("failed to parse public key")?;
+    let public_key = info.subject_public_key.raw_bytes();
+    let curve = info.algorithm.parameters_oid().ok();
+
+    let (algorithm, parameters) = match (info.algorithm.oid, curve) {
+        (RSA_OID, _) => {
+            let public_key = RsaPublicKey::from_public_key_der(document.as_bytes())
+                .context("failed to parse RSA public key")?;
+            (Algorithm::RS256, AlgorithmParameters::RSA(RSAKeyParameters {
+                key_type: RSAKeyType::RSA,
+                n: BASE64URL_NOPAD.encode(&public_key.n().to_bytes_be()),
+                e: BASE64URL_NOPAD.encode(&public_key.e().to_bytes_be()),
+            }))
         }
+        (ED25519_OID, _) => (Algorithm::EdDSA, AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x: BASE64URL_NOPAD.encode(public_key),
+        })),
+        // uncompressed point: 0x04, x and y
+        (EC_OID, Some(P256_OID)) if public_key.len() == 65 => (Algorithm::ES256, AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+            key_type: EllipticCurveKeyType::EC,
+            curve: EllipticCurve::P256,
+            x: BASE64URL_NOPAD.encode(&public_key[1..33]),
+            y: BASE64URL_NOPAD.encode(&public_key[33..]),
+        })),
+        (oid, _) => anyhow::bail!("unsupported public key algorithm {oid}"),
+    };
+
+    let jwk = Jwk {
+        common: CommonParameters {
+            public_key_use: Some(PublicKeyUse::Signature),
+            key_algorithm: Some(KeyAlgorithm::from_str(&format!("{algorithm:?}"))?),
+            ..Default::default()
+        },
+        algorithm: parameters,
+    };
+    Ok((algorithm, jwk))
+}
 
-        let private_key_file: PathBuf = keys_dir.join("private.key");
-        let public_key_file: PathBuf = keys_dir.join("public.key");
+/// Writes PKCS#8 private key and public key in `SubjectPublicKeyInfo` format
+fn write_key_pair(
+    dir: &Path,
+    kid: &str,
+    private_key: &[u8],
+    algorithm: AlgorithmIdentifierRef,
+    public_key: &[u8],
+) -> anyhow::Result<()> {
+    SecretDocument::try_from(private_key)?
+        .write_pem_file(private_key_file(dir, kid), "PRIVATE KEY", LineEnding::default())
+        .context("failed to write private key to file")?;
 
-        if !private_key_file
+    let info = SubjectPublicKeyInfoRef {
+        algorithm,
+        subject_public_key: BitStringRef::from_bytes(public_key)?,
+    };
+    Document::encode_msg(&info)?
+        .write_pem_file(public_key_file(dir, kid), "PUBLIC KEY", LineEnding::default())
+        .context("failed to write public key to file")?;
+    Ok(())
+}
+
+impl Key {
+    fn load(dir: &Path, kid: String) -> anyhow::Result<Self> {
+        let (algorithm, mut jwk) = read_public_key(&public_key_file(dir, &kid))?;
+        jwk.common.key_id = Some(kid.clone());
+        let decoding = DecodingKey::from_jwk(&jwk)
+            .context("failed to create decoding key")?;
+
+        let private_key_file = private_key_file(dir, &kid);
+        let encoding = match private_key_file
             .try_exists()
-            .context("failed to check if private key file exists")?
-        || !public_key_file
+            .context("failed to check if private key file exists")? {
+            true => {
+                let private_key = std::fs::read(&private_key_file)
+                    .context("failed to read private key")?;
+                let encoding = match algorithm {
+                    Algorithm::EdDSA => EncodingKey::from_ed_pem(&private_key),
+                    Algorithm::ES256 => EncodingKey::from_ec_pem(&private_key),
+                    _ => EncodingKey::from_rsa_pem(&private_key),
+                };
+                Some(encoding.context("failed to create encoding key")?)
+            }
+            false => None,
+        };
+
+        Ok(Self { kid, algorithm, jwk, encoding, decoding })
+    }
+
+    fn generate(dir: &Path, algorithm: Algorithm) -> anyhow::Result<String> {
+        let kid = OffsetDateTime::now_utc().unix_timestamp().to_string();
+        if private_key_file(dir, &kid)
             .try_exists()
-            .context("failed to check if public key file exists")? {
-            
-            log::warn!("Keys not found, generating new key pair");
-    
-            let mut rng = rand::thread_rng();
-            let bits = 2048;
-            let private_key = RsaPrivateKey::new(&mut rng, bits)
-                .context("failed to create private key")?;
-            let public_key = RsaPublicKey::from(&private_key);
-    
-            private_key.write_pkcs8_pem_file(&private_key_file, LineEnding::default())
-                .context("failed to write private key to file")?;
-            public_key.write_public_key_pem_file(&public_key_file, LineEnding::default())
-                .context("failed to write public key to file")?;
+            .context("failed to check if private key file exists")? {
+            anyhow::bail!("key {kid} already exists, try again in a second");
         }
-        let private_key = RsaPrivateKey::read_pkcs8_pem_file(&private_key_file)
-            .context("failed to read private key")?;
-        let public_key = RsaPublicKey::read_public_key_pem_file(&public_key_file)
-            .context("failed to read public key")?;
-    
-        Ok(Self{
-            private: private_key,
-            public: public_key
+
+        let rng = SystemRandom::new();
+        match algorithm {
+            Algorithm::EdDSA => {
+                let private_key = Ed25519KeyPair::generate_pkcs8(&rng)
+                    .map_err(|_| anyhow::anyhow!("failed to create private key"))?;
+                let key_pair = Ed25519KeyPair::from_pkcs8(private_key.as_ref())
+                    .map_err(|_| anyhow::anyhow!("failed to read private key"))?;
+                let algorithm = AlgorithmIdentifierRef { oid: ED25519_OID, parameters: None };
+                write_key_pair(dir, &kid, private_key.as_ref(), algorithm, key_pair.public_key().as_ref())?;
+            }
+            Algorithm::ES256 => {
+                let private_key = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+                    .map_err(|_| anyhow::anyhow!("failed to create private key"))?;
+                let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, private_key.as_ref(), &rng)
+                    .map_err(|_| anyhow::anyhow!("failed to read private key"))?;
+                let algorithm = AlgorithmIdentifierRef { oid: EC_OID, parameters: Some(AnyRef::from(&P256_OID)) };
+                write_key_pair(dir, &kid, private_key.as_ref(), algorithm, key_pair.public_key().as_ref())?;
+            }
+            _ => {
+                let mut rng = rand::thread_rng();
+                let bits = 2048;
+                let private_key = RsaPrivateKey::new(&mut rng, bits)
+                    .context("failed to create private key")?;
+                let public_key = RsaPublicKey::from(&private_key);
+
+                private_key.write_pkcs8_pem_file(private_key_file(dir, &kid), LineEnding::default())
+                    .context("failed to write private key to file")?;
+                public_key.write_public_key_pem_file(public_key_file(dir, &kid), LineEnding::default())
+                    .context("failed to write public key to file")?;
+            }
+        }
+
+        log::info!("generated new {algorithm:?} key {kid}");
+        Ok(kid)
+    }
+}
+
+/// Ids of keys in the directory, from the oldest to the newest
+fn kids(dir: &Path) -> anyhow::Result<Vec<String>> {
+    let mut kids: Vec<String> = std::fs::read_dir(dir)
+        .context("failed to read keys directory")?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            let kid = name.strip_suffix(".public.key")?;
+            kid.parse::<i64>().is_ok().then(|| kid.to_string())
         })
+        .collect();
+    kids.sort_by_key(|kid| kid.parse::<i64>().unwrap_or_default());
+    Ok(kids)
+}
+
+/// Gives an id to the key pair from before rotation was supported
+fn migrate_legacy(dir: &Path) -> anyhow::Result<()> {
+    for (file, new_file) in [
+        (dir.join("private.key"), private_key_file(dir, LEGACY_KID)),
+        (dir.join("public.key"), public_key_file(dir, LEGACY_KID)),
+    ] {
+        if file.try_exists().context("failed to check if key file exists")? {
+            log::warn!("Moving {} to {}", file.display(), new_file.display());
+            std::fs::rename(&file, &new_file).context("failed to move key file")?;
+        }
+    }
+    Ok(())
+}
+
+/// Creates the directory and gives an id to legacy keys if needed
+fn prepare_dir(dir: &Path) -> anyhow::Result<()> {
+    if !dir
+        .try_exists()
+        .context("failed to check if keys directory exists")? {
+        log::warn!("Keys directory not found, creating new directory");
+        std::fs::create_dir(dir)
+            .context("failed to create keys directory")?;
+    }
+    migrate_legacy(dir)
+}
+
+/// Loads keys, warns if [ALGORITHM] was changed, but the keys were not rotated yet
+fn init() -> anyhow::Result<KeySet> {
+    let keys = KeySet::load(&keys_dir(), *ALGORITHM)?;
+    if keys.active().algorithm != *ALGORITHM {
+        log::warn!(
+            "Signing algorithm is set to {:?}, but the active key is {:?}, run `rotate-keys` command to switch",
+            *ALGORITHM,
+            keys.active().algorithm
+        );
+    }
+    Ok(keys)
+}
+
+impl KeySet {
+    /// Generates a key with the algorithm if there are none
+    fn load(dir: &Path, algorithm: Algorithm) -> anyhow::Result<Self> {
+        prepare_dir(dir)?;
+
+        let mut kids = kids(dir)?;
+        if kids.is_empty() {
+            log::warn!("Keys not found, generating new key");
+            match Key::generate(dir, algorithm) {
+                Ok(kid) => kids.push(kid),
+                // another server could generate it at the same time
+                Err(e) => {
+                    kids = self::kids(dir)?;
+                    if kids.is_empty() {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+
+        let keys = kids
+            .into_iter()
+            .map(|kid| Key::load(dir, kid))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if !keys.iter().any(|key| key.encoding.is_some()) {
+            anyhow::bail!("no private key found, rotate keys to create a new one");
+        }
+
+        Ok(Self { keys })
+    }
+
+    /// The newest key with a private key, new tokens are signed with it
+    pub fn active(&self) -> &Key {
+        self.keys
+            .iter()
+            .rev()
+            .find(|key| key.encoding.is_some())
+            .expect("there is always an active key")
+    }
+
+    /// Tokens without `kid` were signed before rotation was supported
+    pub fn find(&self, kid: Option<&str>) -> Option<&Key> {
+        let kid = kid.unwrap_or(LEGACY_KID);
+        self.keys.iter().find(|key| key.kid == kid)
     }
-}
\ No newline at end of file
+}
+
+/// Keys to verify a token signed with `kid`.
+/// If the key is unknown, another server may have just rotated keys,
+/// so they are reloaded right away, at most once every [FORCED_RELOAD_INTERVAL].
+pub fn for_kid(kid: Option<&str>) -> Arc<KeySet> {
+    let keys = current();
+    if keys.find(kid).is_some() {
+        return keys;
+    }
+
+    // someone else is reloading right now
+    let Ok(mut last_reload) = LAST_FORCED_RELOAD.try_lock() else {
+        return keys;
+    };
+    if last_reload.is_some_and(|last_reload| last_reload.elapsed() < FORCED_RELOAD_INTERVAL) {
+        return keys;
+    }
+    *last_reload = Some(Instant::now());
+
+    match reload() {
+        Ok(()) => current(),
+        Err(e) => {
+            log::error!("failed to reload keys: {e:?}");
+            keys
+        }
+    }
+}
+
+/// Public keys for verifying tokens, including retired ones
+pub fn jwks() -> JwkSet {
+    JwkSet {
+        keys: current().keys.iter().map(|key| key.jwk.clone()).collect(),
+    }
+}
+
+pub fn reload() -> anyhow::Result<()> {
+    let keys = KeySet::load(&keys_dir(), *ALGORITHM)?;
+    *KEYS.write().unwrap() = Arc::new(keys);
+    Ok(())
+}
+
+/// Reloads keys from disk in the background, so rotation by another process is noticed
+pub fn spawn_reloader() {
+    Lazy::force(&KEYS);
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(RELOAD_INTERVAL);
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            match tokio::task::spawn_blocking(reload).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => log::error!("failed to reload keys: {e:?}"),
+                Err(e) => log::error!("failed to reload keys: {e:?}"),
+            }
+        }
+    });
+}
+
+/// Generates a new active key with [ALGORITHM], retires the previous ones
+/// and removes retired keys which can not have valid tokens anymore.
+/// Returns id of the new key.
+pub fn rotate() -> anyhow::Result<String> {
+    rotate_in(&keys_dir(), *ALGORITHM)
+}
+
+fn rotate_in(dir: &Path, algorithm: Algorithm) -> anyhow::Result<String> {
+    prepare_dir(dir)?;
+
+    let new_kid = Key::generate(dir, algorithm)?;
+    let kids = kids(dir)?;
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+
+    // key is retired when the next one is created
+    for (kid, next_kid) in kids.iter().zip(kids.iter().skip(1)) {
+        let private_key_file = private_key_file(dir, kid);
+        if private_key_file.try_exists().context("failed to check if private key file exists")? {
+            std::fs::remove_file(private_key_file).context("failed to remove private key")?;
+            log::info!("retired key {kid}");
+        }
+
+        let retired_at: i64 = next_kid.parse().unwrap_or_default();
+        if retired_at + REFRESH_LIFE_TIME.whole_seconds() < now {
+            std::fs::remove_file(public_key_file(dir, kid)).context("failed to remove public key")?;
+            log::info!("removed key {kid}");
+        }
+    }
+
+    Ok(new_kid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{Header, Validation};
+    use serde_json::{json, Value};
+
+    /// Empty directory, removed when dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            let dir = std::env::temp_dir().join(format!("keys-test-{}", uuid::Uuid::new_v4()));
+            std::fs::create_dir(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Generates a key with the given id, so several can be made in one second
+    fn generate_as(dir: &Path, algorithm: Algorithm, kid: &str) {
+        let generated = Key::generate(dir, algorithm).unwrap();
+        std::fs::rename(private_key_file(dir, &generated), private_key_file(dir, kid)).unwrap();
+        std::fs::rename(public_key_file(dir, &generated), public_key_file(dir, kid)).unwrap();
+    }
+
+    fn sign(key: &Key, kid: Option<&str>) -> String {
+        let mut header = Header::new(key.algorithm);
+        header.kid = kid.map(str::to_string);
+        let claims = json!({ "sub": "test", "exp": OffsetDateTime::now_utc().unix_timestamp() + 60 });
+        jsonwebtoken::encode(&header, &claims, key.encoding.as_ref().unwrap()).unwrap()
+    }
+
+    fn verify(keys: &KeySet, token: &str) -> bool {
+        let header = jsonwebtoken::decode_header(token).unwrap();
+        let Some(key) = keys.find(header.kid.as_deref()) else {
+            return false;
+        };
+        let validation = Validation::new(key.algorithm);
+        jsonwebtoken::decode::<Value>(token, &key.decoding, &validation).is_ok()
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        for algorithm in [Algorithm::RS256, Algorithm::ES256, Algorithm::EdDSA] {
+            let dir = TempDir::new();
+            let keys = KeySet::load(&dir.0, algorithm).unwrap();
+            let key = keys.active();
+            assert_eq!(key.algorithm, algorithm);
+            assert!(verify(&keys, &sign(key, Some(&key.kid))));
+
+            // public key is published with the id and algorithm
+            let jwk = &key.jwk;
+            assert_eq!(jwk.common.key_id.as_deref(), Some(key.kid.as_str()));
+            assert!(DecodingKey::from_jwk(jwk).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_retired_key_verifies() {
+        let dir = TempDir::new();
+        generate_as(&dir.0, Algorithm::EdDSA, "100");
+        let keys = KeySet::load(&dir.0, Algorithm::EdDSA).unwrap();
+        let token = sign(keys.active(), Some("100"));
+
+        // other algorithm, tokens of both must be valid
+        let new_kid = rotate_in(&dir.0, Algorithm::ES256).unwrap();
+        let keys = KeySet::load(&dir.0, Algorithm::ES256).unwrap();
+        assert_eq!(keys.active().kid, new_kid);
+        assert_eq!(keys.active().algorithm, Algorithm::ES256);
+
+        let retired = keys.find(Some("100")).unwrap();
+        assert!(retired.encoding.is_none());
+        assert!(verify(&keys, &token));
+        assert!(verify(&keys, &sign(keys.active(), Some(&new_kid))));
+    }
+
+    #[test]
+    fn test_legacy_kid() {
+        let dir = TempDir::new();
+        generate_as(&dir.0, Algorithm::RS256, "1");
+        std::fs::rename(private_key_file(&dir.0, "1"), dir.0.join("private.key")).unwrap();
+        std::fs::rename(public_key_file(&dir.0, "1"), dir.0.join("public.key")).unwrap();
+
+        let keys = KeySet::load(&dir.0, Algorithm::RS256).unwrap();
+        assert_eq!(keys.active().kid, LEGACY_KID);
+        assert!(!dir.0.join("public.key").exists());
+
+        let token = sign(keys.active(), None);
+        assert!(verify(&keys, &token));
+        assert!(!verify(&keys, &sign(keys.active(), Some("1"))));
+    }
+
+    #[test]
+    fn test_rotate_removes_expired_keys() {
+        let dir = TempDir::new();
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let expired = now - REFRESH_LIFE_TIME.whole_seconds() - 200;
+        // retired long ago, when the next key was created
+        generate_as(&dir.0, Algorithm::EdDSA, &expired.to_string());
+        // retired recently, tokens signed with it can still be valid
+        let recent = now - REFRESH_LIFE_TIME.whole_seconds() - 100;
+        generate_as(&dir.0, Algorithm::EdDSA, &recent.to_string());
+        let previous = now - 10;
+        generate_as(&dir.0, Algorithm::EdDSA, &previous.to_string());
+
+        let new_kid = rotate_in(&dir.0, Algorithm::EdDSA).unwrap();
+
+        let kids = kids(&dir.0).unwrap();
+        assert_eq!(kids, [recent.to_string(), previous.to_string(), new_kid.clone()]);
+        assert!(!public_key_file(&dir.0, &expired.to_string()).exists());
+        assert!(!private_key_file(&dir.0, &recent.to_string()).exists());
+        assert!(!private_key_file(&dir.0, &previous.to_string()).exists());
+        assert!(private_key_file(&dir.0, &new_kid).exists());
+    }
+}
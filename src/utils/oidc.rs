@@ -0,0 +1,464 @@
+//! # OpenID Connect client
+//! Logging in with external identity providers using authorization code flow with PKCE.
+//! Endpoints of providers are taken from their discovery documents,
+//! ID tokens are checked with keys published by providers.
+//!
+//! Configured by env variables:
+//! - `OIDC_PROVIDERS` - comma separated names of providers, none by default
+//! - `OIDC_{NAME}_ISSUER` - issuer url, discovery document is loaded from
+//!   `{issuer}/.well-known/openid-configuration`
+//! - `OIDC_{NAME}_CLIENT_ID` and `OIDC_{NAME}_CLIENT_SECRET` (not needed for public clients)
+//! - `OIDC_{NAME}_SCOPES` (`openid email profile` by default)
+//! - `OIDC_REDIRECT_URL` (`{APP_URL}/oidc/callback` by default) - page of the app
+//!   which receives `code` and `state` from the provider and sends them to the api
+
+use std::str::FromStr;
+use anyhow::Context;
+use data_encoding::BASE64URL_NOPAD;
+use jsonwebtoken::{
+    jwk::{AlgorithmParameters, EllipticCurve, Jwk, JwkSet},
+    Algorithm, DecodingKey, Validation,
+};
+use once_cell::sync::Lazy;
+use rand::RngCore;
+use reqwest::Client;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+use crate::{http::{HttpError, HttpResult}, utils::email::APP_URL};
+
+pub struct Provider {
+    pub name: String,
+    issuer: String,
+    client_id: String,
+    client_secret: Option<String>,
+    scopes: String,
+    /// Loaded on the first use
+    discovery: OnceCell<Discovery>,
+}
+
+#[derive(Deserialize)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: String,
+}
+
+/// Claims of the ID token, only ones used by the app
+#[derive(Deserialize)]
+pub struct IdTokenClaims {
+    /// Id of the account inside of the provider
+    pub sub: String,
+    pub email: Option<String>,
+    #[serde(default)]
+    pub email_verified: bool,
+    pub name: Option<String>,
+    pub preferred_username: Option<String>,
+    nonce: Option<String>,
+}
+
+/// Proof Key for Code Exchange.
+/// Verifier is kept by the server, only challenge is sent to the provider.
+pub struct Pkce {
+    pub verifier: String,
+    pub challenge: String,
+}
+
+static PROVIDERS: Lazy<Vec<Provider>> = Lazy::new(|| {
+    let Ok(names) = std::env::var("OIDC_PROVIDERS") else {
+        return Vec::new();
+    };
+    names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            let var = |key: &str| std::env::var(format!("OIDC_{}_{key}", name.to_uppercase()));
+            let required = |key: &str| var(key)
+                .unwrap_or_else(|_| panic!("OIDC_{}_{key} env variable is not set", name.to_uppercase()));
+
+            Provider {
+                name: name.to_lowercase(),
+                issuer: required("ISSUER").trim_end_matches('/').to_string(),
+                client_id: required("CLIENT_ID"),
+                client_secret: var("CLIENT_SECRET").ok(),
+                scopes: var("SCOPES").unwrap_or("openid email profile".to_string()),
+                discovery: OnceCell::new(),
+            }
+        })
+        .collect()
+});
+
+static REDIRECT_URL: Lazy<String> = Lazy::new(|| {
+    std::env::var("OIDC_REDIRECT_URL").unwrap_or(format!("{}/oidc/callback", *APP_URL))
+});
+
+pub fn providers() -> impl Iterator<Item = &'static Provider> {
+    PROVIDERS.iter()
+}
+
+pub fn provider(name: &str) -> Option<&'static Provider> {
+    PROVIDERS.iter().find(|provider| provider.name == name)
+}
+
+/// Random url safe string for `state`, `nonce` and PKCE verifier
+pub fn random_string() -> String {
+    let mut bytes = [0; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    BASE64URL_NOPAD.encode(&bytes)
+}
+
+impl Pkce {
+    /// Uses `S256` method
+    pub fn generate() -> Self {
+        Self::from_verifier(random_string())
+    }
+
+    fn from_verifier(verifier: String) -> Self {
+        let challenge = BASE64URL_NOPAD.encode(&Sha256::digest(verifier.as_bytes()));
+        Self { verifier, challenge }
+    }
+}
+
+impl Provider {
+    async fn discovery(&self, client: &Client) -> HttpResult<&Discovery> {
+        self.discovery
+            .get_or_try_init(|| async {
+                let url = format!("{}/.well-known/openid-configuration", self.issuer);
+                let discovery: Discovery = client.get(url)
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .context("failed to request discovery document")?
+                    .json()
+                    .await
+                    .context("failed to parse discovery document")?;
+
+                if discovery.issuer.trim_end_matches('/') != self.issuer {
+                    return Err(anyhow::anyhow!("issuer of {} does not match discovery document", self.name).into());
+                }
+                Ok(discovery)
+            })
+            .await
+    }
+
+    /// Url of the provider's login page, user is sent there
+    pub async fn authorization_url(
+        &self,
+        client: &Client,
+        state: &str,
+        nonce: &str,
+        challenge: &str,
+    ) -> HttpResult<String> {
+        let discovery = self.discovery(client).await?;
+        let query = [
+            ("response_type", "code"),
+            ("client_id", &self.client_id),
+            ("redirect_uri", &REDIRECT_URL),
+            ("scope", &self.scopes),
+            ("state", state),
+            ("nonce", nonce),
+            ("code_challenge", challenge),
+            ("code_challenge_method", "S256"),
+        ]
+        .map(|(key, value)| format!("{key}={}", urlencoding::encode(value)))
+        .join("&");
+
+        Ok(format!("{}?{query}", discovery.authorization_endpoint))
+    }
+
+    /// Exchanges the code received from the provider for a checked ID token
+    pub async fn exchange(
+        &self,
+        client: &Client,
+        code: &str,
+        verifier: &str,
+        nonce: &str,
+    ) -> HttpResult<IdTokenClaims> {
+        let discovery = self.discovery(client).await?;
+
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &REDIRECT_URL),
+            ("client_id", &self.client_id),
+            ("code_verifier", verifier),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret));
+        }
+
+        let response = client.post(&discovery.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .context("failed to request tokens from provider")?;
+        if response.status().is_client_error() {
+            log::warn!("{} rejected the code: {}", self.name, response.text().await.unwrap_or_default());
+            return Err(HttpError::bad_request("Code is invalid or expired"));
+        }
+        let tokens: TokenResponse = response
+            .error_for_status()
+            .context("failed to request tokens from provider")?
+            .json()
+            .await
+            .context("failed to parse tokens from provider")?;
+
+        self.validate(client, discovery, &tokens.id_token, nonce).await
+    }
+
+    async fn validate(
+        &self,
+        client: &Client,
+        discovery: &Discovery,
+        id_token: &str,
+        nonce: &str,
+    ) -> HttpResult<IdTokenClaims> {
+        let header = jsonwebtoken::decode_header(id_token)
+            .context("failed to parse ID token header")?;
+
+        // keys are loaded every time, they can be rotated and logins are not frequent
+        let keys: JwkSet = client.get(&discovery.jwks_uri)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .context("failed to request provider keys")?
+            .json()
+            .await
+            .context("failed to parse provider keys")?;
+        let key = match &header.kid {
+            Some(kid) => keys.find(kid),
+            None => keys.keys.first(),
+        }
+        .context("ID token is signed with unknown key")?;
+        // the header could name any algorithm, only the one of the key is accepted
+        let algorithm = key_algorithm(key)?;
+        if header.alg != algorithm {
+            return Err(anyhow::anyhow!(
+                "ID token of {} is signed with {:?}, but the key is for {algorithm:?}",
+                self.name,
+                header.alg
+            ).into());
+        }
+        let key = DecodingKey::from_jwk(key).context("failed to read provider key")?;
+
+        let mut validation = Validation::new(algorithm);
+        validation.set_issuer(&[&discovery.issuer]);
+        validation.set_audience(&[&self.client_id]);
+
+        let claims: IdTokenClaims = jsonwebtoken::decode(id_token, &key, &validation)
+            .context("failed to validate ID token")?
+            .claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(anyhow::anyhow!("nonce of ID token from {} does not match", self.name).into());
+        }
+        Ok(claims)
+    }
+}
+
+/// `alg` is optional in JWK, the usual one for the key type is used without it.
+/// Symmetric keys are rejected, their secret is shared with the provider.
+fn key_algorithm(key: &Jwk) -> anyhow::Result<Algorithm> {
+    let algorithm = match (&key.common.key_algorithm, &key.algorithm) {
+        (_, AlgorithmParameters::OctetKey(_)) => anyhow::bail!("provider key is symmetric"),
+        (Some(algorithm), _) => Algorithm::from_str(&algorithm.to_string())
+            .context("provider key is not for signing")?,
+        (None, AlgorithmParameters::RSA(_)) => Algorithm::RS256,
+        (None, AlgorithmParameters::EllipticCurve(parameters)) => match parameters.curve {
+            EllipticCurve::P384 => Algorithm::ES384,
+            _ => Algorithm::ES256,
+        },
+        (None, AlgorithmParameters::OctetKeyPair(_)) => Algorithm::EdDSA,
+    };
+    if matches!(algorithm, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+        anyhow::bail!("provider key is for HMAC");
+    }
+    Ok(algorithm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Json, Router};
+    use jsonwebtoken::{EncodingKey, Header};
+    use ring::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+    };
+    use serde_json::{json, Value};
+    use time::OffsetDateTime;
+
+    const CLIENT_ID: &str = "app";
+    const NONCE: &str = "nonce";
+
+    /// Provider with discovery document and keys served from a local port
+    struct MockProvider {
+        provider: Provider,
+        issuer: String,
+        ed_key: EncodingKey,
+        ec_key: EncodingKey,
+        ed_public_key: Vec<u8>,
+    }
+
+    impl MockProvider {
+        async fn start() -> Self {
+            let random = SystemRandom::new();
+            let ed_pkcs8 = Ed25519KeyPair::generate_pkcs8(&random).unwrap();
+            let ed_public_key = Ed25519KeyPair::from_pkcs8(ed_pkcs8.as_ref())
+                .unwrap()
+                .public_key()
+                .as_ref()
+                .to_vec();
+            let ec_pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &random).unwrap();
+            let ec_public_key = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, ec_pkcs8.as_ref(), &random)
+                .unwrap()
+                .public_key()
+                .as_ref()
+                .to_vec();
+
+            // uncompressed point: 0x04, x and y
+            let jwks = json!({
+                "keys": [
+                    {
+                        "kty": "OKP",
+                        "crv": "Ed25519",
+                        "kid": "ed",
+                        "x": BASE64URL_NOPAD.encode(&ed_public_key),
+                    },
+                    {
+                        "kty": "EC",
+                        "crv": "P-256",
+                        "kid": "ec",
+                        "alg": "ES256",
+                        "x": BASE64URL_NOPAD.encode(&ec_public_key[1..33]),
+                        "y": BASE64URL_NOPAD.encode(&ec_public_key[33..]),
+                    },
+                ]
+            });
+
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let issuer = format!("http://{}", listener.local_addr().unwrap());
+            let discovery = json!({
+                "issuer": issuer,
+                "authorization_endpoint": format!("{issuer}/authorize"),
+                "token_endpoint": format!("{issuer}/token"),
+                "jwks_uri": format!("{issuer}/jwks"),
+            });
+            let router = Router::new()
+                .route("/.well-known/openid-configuration", get(|| async move { Json(discovery) }))
+                .route("/jwks", get(|| async move { Json(jwks) }));
+            tokio::spawn(async move { axum::serve(listener, router).await });
+
+            let provider = Provider {
+                name: "mock".to_string(),
+                issuer: issuer.clone(),
+                client_id: CLIENT_ID.to_string(),
+                client_secret: None,
+                scopes: "openid".to_string(),
+                discovery: OnceCell::new(),
+            };
+
+            Self {
+                provider,
+                issuer,
+                ed_key: EncodingKey::from_ed_der(ed_pkcs8.as_ref()),
+                ec_key: EncodingKey::from_ec_der(ec_pkcs8.as_ref()),
+                ed_public_key,
+            }
+        }
+
+        fn claims(&self) -> Value {
+            json!({
+                "iss": self.issuer,
+                "aud": CLIENT_ID,
+                "sub": "12345",
+                "email": "user@example.com",
+                "email_verified": true,
+                "nonce": NONCE,
+                "exp": OffsetDateTime::now_utc().unix_timestamp() + 300,
+            })
+        }
+
+        fn sign(&self, algorithm: Algorithm, kid: &str, key: &EncodingKey, claims: &Value) -> String {
+            let mut header = Header::new(algorithm);
+            header.kid = Some(kid.to_string());
+            jsonwebtoken::encode(&header, claims, key).unwrap()
+        }
+
+        async fn validate(&self, id_token: &str) -> HttpResult<IdTokenClaims> {
+            let client = Client::new();
+            let discovery = self.provider.discovery(&client).await?;
+            self.provider.validate(&client, discovery, id_token, NONCE).await
+        }
+    }
+
+    #[test]
+    fn test_pkce() {
+        // example from RFC 7636
+        let pkce = Pkce::from_verifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk".to_string());
+        assert_eq!(pkce.challenge, "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM");
+        assert_eq!(Pkce::generate().verifier.len(), 43);
+    }
+
+    #[tokio::test]
+    async fn test_valid_id_token() {
+        let mock = MockProvider::start().await;
+
+        let token = mock.sign(Algorithm::EdDSA, "ed", &mock.ed_key, &mock.claims());
+        let claims = mock.validate(&token).await.unwrap();
+        assert_eq!(claims.sub, "12345");
+        assert_eq!(claims.email.as_deref(), Some("user@example.com"));
+        assert!(claims.email_verified);
+
+        let token = mock.sign(Algorithm::ES256, "ec", &mock.ec_key, &mock.claims());
+        assert!(mock.validate(&token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_key_mismatch() {
+        let mock = MockProvider::start().await;
+        let claims = mock.claims();
+
+        let token = mock.sign(Algorithm::EdDSA, "unknown", &mock.ed_key, &claims);
+        assert!(mock.validate(&token).await.is_err());
+
+        // valid signature, but the header names the other key
+        let token = mock.sign(Algorithm::ES256, "ed", &mock.ec_key, &claims);
+        assert!(mock.validate(&token).await.is_err());
+
+        // public key used as an HMAC secret
+        let hmac_key = EncodingKey::from_secret(&mock.ed_public_key);
+        let token = mock.sign(Algorithm::HS256, "ed", &hmac_key, &claims);
+        assert!(mock.validate(&token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_claims() {
+        let mock = MockProvider::start().await;
+        let invalid = [
+            ("iss", json!("https://attacker.example.com")),
+            ("aud", json!("another-app")),
+            ("nonce", json!("another-nonce")),
+            ("exp", json!(OffsetDateTime::now_utc().unix_timestamp() - 3600)),
+        ];
+
+        for (claim, value) in invalid {
+            let mut claims = mock.claims();
+            claims[claim] = value;
+            let token = mock.sign(Algorithm::EdDSA, "ed", &mock.ed_key, &claims);
+            assert!(mock.validate(&token).await.is_err(), "accepted wrong {claim}");
+        }
+
+        let mut claims = mock.claims();
+        claims.as_object_mut().unwrap().remove("nonce");
+        let token = mock.sign(Algorithm::EdDSA, "ed", &mock.ed_key, &claims);
+        assert!(mock.validate(&token).await.is_err());
+    }
+}
@@ -8,6 +8,8 @@ use argon2::{
     Algorithm,
     Version,
     Params,
+    ParamsBuilder,
+    KeyId,
     password_hash::{
         PasswordHash,
         PasswordHasher,
@@ -17,19 +19,56 @@ use argon2::{
     }
 };
 use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
 use crate::http::HttpResult;
 
+fn env_number(name: &str, default: u32) -> u32 {
+    std::env::var(name)
+        .map(|value| value.parse().unwrap_or_else(|_| panic!("failed to parse {name} env variable")))
+        .unwrap_or(default)
+}
+
+/// Server-side secret mixed into every new hash, set by `PASSWORD_PEPPER` env variable.
+/// It is not stored in the database, so leaked hashes can not be cracked without it.
+static PEPPER: Lazy<Option<Vec<u8>>> = Lazy::new(|| {
+    std::env::var("PASSWORD_PEPPER").ok().map(String::into_bytes)
+});
+
+/// Identifies the pepper inside of hashes without revealing it,
+/// so hashes made before it was set (or with another one) are recognised
+static PEPPER_ID: Lazy<Option<KeyId>> = Lazy::new(|| {
+    PEPPER.as_ref().map(|pepper| KeyId::new(&Sha256::digest(pepper)[..4]).unwrap())
+});
+
+/// Parameters of new hashes, set by env variables:
+/// `ARGON2_MEMORY` in KiB (`32768` by default), `ARGON2_ITERATIONS` (`2`)
+/// and `ARGON2_PARALLELISM` (`1`).
+/// Hashes with other parameters are upgraded on login.
+static PARAMS: Lazy<Params> = Lazy::new(|| {
+    let mut builder = ParamsBuilder::new();
+    builder
+        .m_cost(env_number("ARGON2_MEMORY", 2_u32.pow(15)))
+        .t_cost(env_number("ARGON2_ITERATIONS", 2))
+        .p_cost(env_number("ARGON2_PARALLELISM", 1))
+        .output_len(32);
+    if let Some(pepper_id) = *PEPPER_ID {
+        builder.keyid(pepper_id);
+    }
+    builder.build().expect("invalid Argon2 parameters")
+});
+
 static ARGON2: Lazy<Argon2> = Lazy::new(|| {
-    Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(
-            2_u32.pow(15),
-            2,
-            1,
-            Some(32)
-        ).unwrap()
-    )
+    let params = PARAMS.clone();
+    match &*PEPPER {
+        Some(pepper) => Argon2::new_with_secret(pepper, Algorithm::Argon2id, Version::V0x13, params)
+            .expect("PASSWORD_PEPPER is too long"),
+        None => Argon2::new(Algorithm::Argon2id, Version::V0x13, params),
+    }
+});
+
+/// Verifies hashes made without the pepper
+static ARGON2_WITHOUT_PEPPER: Lazy<Argon2> = Lazy::new(|| {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, PARAMS.clone())
 });
 
 // Those are called green threads
@@ -54,16 +93,35 @@ pub async fn verify_password(password: String, password_hash: String) -> HttpRes
     tokio::task::spawn_blocking(move || -> HttpResult<()> {
         let password_hash = PasswordHash::new(&password_hash)
             .map_err(|e| anyhow!("failed to get password hash {}", e))?;
-        ARGON2.verify_password(password.as_bytes(), &password_hash)
-            // .map_err(|e| match e {
-            //     argon2::password_hash::Error::Password => HttpError::Unauthorized,
-            //     _ => anyhow!("failed to verify password: {}", e).into(),
-            // })
+        let params = Params::try_from(&password_hash)
+            .map_err(|e| anyhow!("failed to get password hash params {}", e))?;
+
+        let argon2 = match params.keyid() {
+            [] => &*ARGON2_WITHOUT_PEPPER,
+            keyid if Some(keyid) == PEPPER_ID.as_ref().map(KeyId::as_bytes) => &*ARGON2,
+            _ => return Err(anyhow!("password was hashed with another pepper").into()),
+        };
+        argon2.verify_password(password.as_bytes(), &password_hash)
             .map_err(|e| anyhow!("failed to verify password: {}", e).into())
     })
     .await.context("failed to verify password")?
 }
 
+/// Whether the hash was made with other parameters or pepper than new ones.
+/// Should be checked after the password is verified, so it can be hashed again.
+pub fn needs_rehash(password_hash: &str) -> bool {
+    let Ok(password_hash) = PasswordHash::new(password_hash) else {
+        return true;
+    };
+    let Ok(params) = Params::try_from(&password_hash) else {
+        return true;
+    };
+
+    password_hash.algorithm != Algorithm::Argon2id.ident()
+        || password_hash.version != Some(Version::V0x13.into())
+        || params != *PARAMS
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -73,6 +131,7 @@ mod tests {
     async fn test_password() {
         let password = "123456".to_string();
         let hash = hash_password(password.clone()).await.expect("failed to hash password");
+        assert!(!needs_rehash(&hash));
         verify_password(password, hash).await.expect("failed to verify password");
     }
 }
\ No newline at end of file
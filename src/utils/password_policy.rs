@@ -0,0 +1,123 @@
+//! # Password policy
+//! Checked every time a password is set.
+//!
+//! Configured by env variables:
+//! - `PASSWORD_MIN_LENGTH` (`8` by default)
+//! - `PASSWORD_MAX_LENGTH` (`128` by default), hashing long passwords takes longer
+//! - `PASSWORD_CHARACTER_CLASSES` (`2` by default) - how many of lowercase letters,
+//!   uppercase letters, digits and other symbols password must contain
+//! - `BREACHED_PASSWORDS_DIR` (not set by default) - directory with leaked password hashes.
+//!   It has the same format as the "Have I Been Pwned" range API: SHA-1 hashes are split
+//!   by the first 5 hex characters into `{prefix}.txt` files with `{suffix}:{count}` lines.
+//!   Only one small file has to be read to check a password.
+
+use std::path::PathBuf;
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use sha1::{Digest, Sha1};
+use crate::http::{HttpError, HttpResult};
+
+const PREFIX_LENGTH: usize = 5;
+
+fn env_number(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .map(|value| value.parse().unwrap_or_else(|_| panic!("failed to parse {name} env variable")))
+        .unwrap_or(default)
+}
+
+static MIN_LENGTH: Lazy<usize> = Lazy::new(|| env_number("PASSWORD_MIN_LENGTH", 8));
+static MAX_LENGTH: Lazy<usize> = Lazy::new(|| env_number("PASSWORD_MAX_LENGTH", 128));
+static CHARACTER_CLASSES: Lazy<usize> = Lazy::new(|| env_number("PASSWORD_CHARACTER_CLASSES", 2));
+static BREACHED_PASSWORDS_DIR: Lazy<Option<PathBuf>> = Lazy::new(|| {
+    std::env::var("BREACHED_PASSWORDS_DIR").ok().map(PathBuf::from)
+});
+
+fn character_classes(password: &str) -> usize {
+    let classes = [
+        password.chars().any(|c| c.is_lowercase()),
+        password.chars().any(|c| c.is_uppercase()),
+        password.chars().any(|c| c.is_ascii_digit()),
+        password.chars().any(|c| !c.is_alphanumeric()),
+    ];
+    classes.into_iter().filter(|&class| class).count()
+}
+
+/// Returns messages for every broken rule
+fn broken_rules(password: &str, username: &str, email: &str) -> Vec<String> {
+    let mut errors = Vec::new();
+    let length = password.chars().count();
+
+    if length < *MIN_LENGTH {
+        errors.push(format!("Password must be at least {} characters", *MIN_LENGTH));
+    }
+    if length > *MAX_LENGTH {
+        errors.push(format!("Password must be at most {} characters", *MAX_LENGTH));
+    }
+    if character_classes(password) < *CHARACTER_CLASSES {
+        errors.push(format!(
+            "Password must contain at least {} of: lowercase letters, uppercase letters, digits, symbols",
+            *CHARACTER_CLASSES
+        ));
+    }
+
+    let lowercase = password.to_lowercase();
+    let email_name = email.split('@').next().unwrap_or_default();
+    if [username, email_name]
+        .into_iter()
+        .any(|part| part.len() >= 3 && lowercase.contains(&part.to_lowercase()))
+    {
+        errors.push("Password must not contain username or email".to_string());
+    }
+
+    errors
+}
+
+async fn is_breached(password: &str) -> HttpResult<bool> {
+    let Some(dir) = &*BREACHED_PASSWORDS_DIR else {
+        return Ok(false);
+    };
+
+    let hash = hex::encode_upper(Sha1::digest(password.as_bytes()));
+    let (prefix, suffix) = hash.split_at(PREFIX_LENGTH);
+
+    let hashes = match tokio::fs::read_to_string(dir.join(format!("{prefix}.txt"))).await {
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        result => result.context("failed to read breached passwords")?,
+    };
+
+    Ok(hashes
+        .lines()
+        .filter_map(|line| line.split(':').next())
+        .any(|line_suffix| line_suffix.trim().eq_ignore_ascii_case(suffix)))
+}
+
+/// Returns [HttpError::UnprocessableEntity] with every broken rule
+pub async fn check(password: &str, username: &str, email: &str) -> HttpResult<()> {
+    let mut errors = broken_rules(password, username, email);
+
+    // no need to look up passwords which are rejected anyway
+    if errors.is_empty() && is_breached(password).await? {
+        errors.push("Password was found in a data breach, choose another one".to_string());
+    }
+
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(HttpError::unprocessable_entity(
+        errors.into_iter().map(|error| ("password", error)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_password_policy() {
+        assert!(broken_rules("correct-horse", "user", "user@example.com").is_empty());
+        assert_eq!(broken_rules("short", "user", "user@example.com").len(), 2);
+        assert_eq!(broken_rules("MyNameIsAdmin1", "admin", "user@example.com").len(), 1);
+        assert_eq!(broken_rules("john.doe-2000", "user", "john.doe@example.com").len(), 1);
+        assert_eq!(broken_rules(&"a1".repeat(100), "user", "user@example.com").len(), 1);
+    }
+}
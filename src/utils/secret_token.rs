@@ -0,0 +1,36 @@
+//! # Random one-time tokens
+//! Used where a signed token does not fit, because it must be usable only once
+//! (e.g. password reset). Only the hash of the token is stored,
+//! so tokens can not be used by someone who can read the database.
+//! Tokens are random enough, so a fast hash is fine here, unlike with passwords.
+
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+const TOKEN_LENGTH: usize = 32;
+
+/// Generates a new token, returns it with its hash
+pub fn generate() -> (String, String) {
+    let mut bytes = [0; TOKEN_LENGTH];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+    let hash = hash(&token);
+    (token, hash)
+}
+
+pub fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_token() {
+        let (token, token_hash) = generate();
+        assert_eq!(token.len(), TOKEN_LENGTH * 2);
+        assert_eq!(hash(&token), token_hash);
+        assert_ne!(generate().0, token);
+    }
+}
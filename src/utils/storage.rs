@@ -0,0 +1,84 @@
+//! # Storing uploaded files on disk
+//! Files are saved in the directory from `UPLOAD_DIR` env variable (`data/uploads` by default),
+//! grouped into folders. Each file is named by the id of its `upload` row.
+//! Limits for uploaded files are configured here as well.
+
+use std::path::PathBuf;
+use once_cell::sync::Lazy;
+use uuid::Uuid;
+
+const DEFAULT_UPLOAD_DIR: &str = "data/uploads";
+const DEFAULT_MAX_SIZE: u64 = 10 * 1024 * 1024;
+const DEFAULT_MAX_FILES: usize = 10;
+/// Room for boundaries and headers of multipart fields
+const MULTIPART_OVERHEAD: usize = 64 * 1024;
+const DEFAULT_CONTENT_TYPES: &str = "image/png,image/jpeg,image/gif,image/webp,application/pdf,text/plain";
+
+/// Root directory for all uploaded files
+pub static UPLOAD_DIR: Lazy<PathBuf> = Lazy::new(|| {
+    std::env::var("UPLOAD_DIR")
+        .unwrap_or(DEFAULT_UPLOAD_DIR.to_string())
+        .into()
+});
+
+/// Maximum size of one file in bytes
+pub static MAX_SIZE: Lazy<u64> = Lazy::new(|| {
+    std::env::var("UPLOAD_MAX_SIZE")
+        .map(|size| size.parse().expect("failed to parse UPLOAD_MAX_SIZE env variable"))
+        .unwrap_or(DEFAULT_MAX_SIZE)
+});
+
+/// Maximum number of files in one upload request
+pub static MAX_FILES: Lazy<usize> = Lazy::new(|| {
+    std::env::var("UPLOAD_MAX_FILES")
+        .map(|files| files.parse().expect("failed to parse UPLOAD_MAX_FILES env variable"))
+        .unwrap_or(DEFAULT_MAX_FILES)
+});
+
+/// Content types which are allowed to be uploaded
+pub static CONTENT_TYPES: Lazy<Vec<String>> = Lazy::new(|| {
+    std::env::var("UPLOAD_CONTENT_TYPES")
+        .unwrap_or(DEFAULT_CONTENT_TYPES.to_string())
+        .split(',')
+        .map(|content_type| content_type.trim().to_lowercase())
+        .filter(|content_type| !content_type.is_empty())
+        .collect()
+});
+
+/// Images which browsers can show inline, everything else is served as a download,
+/// so uploaded HTML or SVG can not run scripts on our origin
+pub const INLINE_CONTENT_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// Limit for the whole multipart body with this many files of [MAX_SIZE]
+pub fn body_limit(files: usize) -> usize {
+    (*MAX_SIZE as usize).saturating_mul(files).saturating_add(MULTIPART_OVERHEAD)
+}
+
+/// Path to the folder, where files of this kind are stored
+pub fn folder_path(folder: &str) -> PathBuf {
+    UPLOAD_DIR.join(folder)
+}
+
+/// Path to the uploaded file.\
+/// Extension is expected to include a dot, as in `upload` table: `.webp`
+pub fn file_path(folder: &str, id: Uuid, extension: &str) -> PathBuf {
+    folder_path(folder).join(format!("{id}{extension}"))
+}
+
+/// Path to another version of the uploaded file, e.g. a smaller image
+pub fn variant_path(folder: &str, id: Uuid, variant: &str, extension: &str) -> PathBuf {
+    folder_path(folder).join(format!("{id}_{variant}{extension}"))
+}
+
+/// Takes the extension out of user provided file name.
+/// Returns it with a leading dot, or an empty string if there is no reasonable extension.
+pub fn extension(file_name: &str) -> String {
+    std::path::Path::new(file_name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .filter(|extension| {
+            extension.len() <= 10 && extension.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+        .map(|extension| format!(".{}", extension.to_lowercase()))
+        .unwrap_or_default()
+}
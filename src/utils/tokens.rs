@@ -4,21 +4,17 @@
 //! `access` is used to access protected routes.
 //! `refresh` is used to generate a new pair when `access` token expires.
 //! Tokens are generated when user logs in.
-//! To sign tokens, RSA keys are used.
-//! Keys are taken from [Keys][super::keys] module.
+//! Tokens are signed with RSA, P-256 or Ed25519 keys, depending on configuration.
+//! Keys are taken from [Keys][super::keys] module,
+//! id of the signing key is set in `kid` header, so tokens stay valid after rotation.
 
 use anyhow::Context;
-use rsa::pkcs8::{
-    EncodePrivateKey,
-    EncodePublicKey,
-    LineEnding
-};
-use jsonwebtoken::{EncodingKey, DecodingKey, Validation, Algorithm, Header};
+use jsonwebtoken::{Validation, Header};
 use time::{Duration, OffsetDateTime};
 use uuid::Uuid;
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
 use once_cell::sync::Lazy;
-use super::keys::KEY_PAIR;
+use super::keys;
 use crate::http::HttpResult;
 
 /// Tokens are sent to user as a pair.
@@ -40,47 +36,122 @@ pub struct Claims {
     /// Token id
     pub jti: Uuid,
     /// Audience (what the token is intended for)\
-    /// `"api"` or `"refresh"`
+    /// [ACCESS_AUDIENCE] or [REFRESH_AUDIENCE]
     pub aud: String,
     /// User id (to identify, whose token is this)
     pub user_id: Uuid,
     /// Expiration time (unix timestamp)
     pub exp: i64,
     /// Issued at (created at) (unix timestamp)
-    pub iat: i64
+    pub iat: i64,
+    /// Only set in access tokens
+    #[serde(flatten)]
+    pub grants: Grants
 }
 
-const ACCESS_LIFE_TIME: Duration = Duration::minutes(10);
-const REFRESH_LIFE_TIME: Duration = Duration::days(30);
+/// Roles of the user and permissions they give, at the time the token was created
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct Grants {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub permissions: Vec<String>
+}
+
+/// Claims of the token sent in e-mail verification link.
+/// It is only valid while user has the same e-mail.
+#[derive(Serialize, Deserialize)]
+pub struct EmailClaims {
+    pub aud: String,
+    pub user_id: Uuid,
+    pub email: String,
+    pub exp: i64
+}
+
+/// Claims of the ticket given after checking the password of a user with two-factor authentication.
+/// It is exchanged for a token pair together with a valid code.
+#[derive(Serialize, Deserialize)]
+pub struct MfaClaims {
+    pub aud: String,
+    pub user_id: Uuid,
+    pub exp: i64
+}
+
+const ACCESS_AUDIENCE: &str = "api";
+const REFRESH_AUDIENCE: &str = "refresh";
+const EMAIL_AUDIENCE: &str = "verify-email";
+const MFA_AUDIENCE: &str = "mfa";
+
+pub const ACCESS_LIFE_TIME: Duration = Duration::minutes(10);
+pub const REFRESH_LIFE_TIME: Duration = Duration::days(30);
+const EMAIL_LIFE_TIME: Duration = Duration::days(1);
+const MFA_LIFE_TIME: Duration = Duration::minutes(5);
 
-static ENCODING_KEY: Lazy<EncodingKey> = Lazy::new(|| {
-    let key = KEY_PAIR.private.to_pkcs8_pem(LineEnding::default()).unwrap();
-    EncodingKey::from_rsa_pem(key.as_bytes()).unwrap()
+// algorithms are set by the key which signed the token
+static ACCESS_VALIDATION: Lazy<Validation> = Lazy::new(|| {
+    let mut validation = Validation::default();
+    validation.set_audience(&[ACCESS_AUDIENCE]);
+    validation
+});
+static REFRESH_VALIDATION: Lazy<Validation> = Lazy::new(|| {
+    let mut validation = Validation::default();
+    validation.set_audience(&[REFRESH_AUDIENCE]);
+    validation
 });
-static DECODING_KEY: Lazy<DecodingKey> = Lazy::new(|| {
-    let key = KEY_PAIR.public.to_public_key_pem(LineEnding::default()).unwrap();
-    DecodingKey::from_rsa_pem(key.as_bytes()).unwrap()
+static EMAIL_VALIDATION: Lazy<Validation> = Lazy::new(|| {
+    let mut validation = Validation::default();
+    validation.set_audience(&[EMAIL_AUDIENCE]);
+    validation
 });
-static HEADER: Lazy<Header> = Lazy::new(|| Header::new(Algorithm::RS256));
-static VALIDATION: Lazy<Validation> = Lazy::new(|| {
-    let mut validation = Validation::new(Algorithm::RS256);
-    validation.set_audience(&["api", "refresh"]);
+static MFA_VALIDATION: Lazy<Validation> = Lazy::new(|| {
+    let mut validation = Validation::default();
+    validation.set_audience(&[MFA_AUDIENCE]);
     validation
 });
 
+/// Signs claims with the active key
+fn sign(claims: &impl Serialize) -> anyhow::Result<String> {
+    let keys = keys::current();
+    let key = keys.active();
+    let mut header = Header::new(key.algorithm);
+    header.kid = Some(key.kid.clone());
+    let encoding_key = key.encoding.as_ref().context("active key has no private key")?;
+    Ok(jsonwebtoken::encode(&header, claims, encoding_key)?)
+}
+
+/// Verifies the token with the key it was signed with
+fn verify<T: DeserializeOwned>(token: &str, validation: &Validation) -> anyhow::Result<T> {
+    let header = jsonwebtoken::decode_header(token)?;
+    let keys = keys::for_kid(header.kid.as_deref());
+    let key = keys.find(header.kid.as_deref()).context("token is signed with unknown key")?;
+    // every key only accepts its own algorithm, whatever is set in the header
+    let mut validation = validation.clone();
+    validation.algorithms = vec![key.algorithm];
+    Ok(jsonwebtoken::decode(token, &key.decoding, &validation)?.claims)
+}
+
 impl Claims {
-    /// Try to parse token string into valid claims
-    pub fn parse(token: &str) -> HttpResult<Self> {
+    fn parse(token: &str, validation: &Validation) -> HttpResult<Self> {
         Ok(
-            jsonwebtoken::decode(token, &DECODING_KEY, &VALIDATION)
+            verify(token, validation)
             .context("failed to parse token")?
-            .claims
         )
     }
+
+    /// Try to parse access token string into valid claims
+    pub fn parse_access(token: &str) -> HttpResult<Self> {
+        Self::parse(token, &ACCESS_VALIDATION)
+    }
+
+    /// Try to parse refresh token string into valid claims.
+    /// Access tokens are rejected, so they can not be used to get a new pair.
+    pub fn parse_refresh(token: &str) -> HttpResult<Self> {
+        Self::parse(token, &REFRESH_VALIDATION)
+    }
 }
 
 impl TokenPair {
-    pub async fn new(user_id: Uuid) -> HttpResult<TokenPair> {
+    pub async fn new(user_id: Uuid, grants: Grants) -> HttpResult<TokenPair> {
         let jti = Uuid::new_v4();
 
         let now = OffsetDateTime::now_utc();
@@ -90,23 +161,25 @@ impl TokenPair {
 
         let access_claims = Claims {
             jti,
-            aud: "api".to_string(),
-            user_id: user_id,
+            aud: ACCESS_AUDIENCE.to_string(),
+            user_id,
             exp: access_exp,
-            iat
+            iat,
+            grants
         };
 
         let refresh_claims = Claims {
             jti,
-            aud: "refresh".to_string(),
-            user_id: user_id,
+            aud: REFRESH_AUDIENCE.to_string(),
+            user_id,
             exp: refresh_exp,
-            iat
+            iat,
+            grants: Grants::default()
         };
 
-        let access_token = jsonwebtoken::encode(&HEADER, &access_claims, &ENCODING_KEY)
+        let access_token = sign(&access_claims)
             .context("failed to encode access token")?;
-        let refresh_token = jsonwebtoken::encode(&HEADER, &refresh_claims, &ENCODING_KEY)
+        let refresh_token = sign(&refresh_claims)
             .context("failed to encode refresh token")?;
 
         log::debug!("created new token pair\nid: {}\nuser_id: {}", jti, user_id);
@@ -119,6 +192,53 @@ impl TokenPair {
     }
 }
 
+impl EmailClaims {
+    pub fn encode(user_id: Uuid, email: String) -> HttpResult<String> {
+        let claims = Self {
+            aud: EMAIL_AUDIENCE.to_string(),
+            user_id,
+            email,
+            exp: (OffsetDateTime::now_utc() + EMAIL_LIFE_TIME).unix_timestamp()
+        };
+
+        Ok(
+            sign(&claims)
+            .context("failed to encode e-mail token")?
+        )
+    }
+
+    /// Try to parse e-mail verification token string into valid claims
+    pub fn parse(token: &str) -> HttpResult<Self> {
+        Ok(
+            verify(token, &EMAIL_VALIDATION)
+            .context("failed to parse e-mail token")?
+        )
+    }
+}
+
+impl MfaClaims {
+    pub fn encode(user_id: Uuid) -> HttpResult<String> {
+        let claims = Self {
+            aud: MFA_AUDIENCE.to_string(),
+            user_id,
+            exp: (OffsetDateTime::now_utc() + MFA_LIFE_TIME).unix_timestamp()
+        };
+
+        Ok(
+            sign(&claims)
+            .context("failed to encode mfa ticket")?
+        )
+    }
+
+    /// Try to parse mfa ticket string into valid claims
+    pub fn parse(token: &str) -> HttpResult<Self> {
+        Ok(
+            verify(token, &MFA_VALIDATION)
+            .context("failed to parse mfa ticket")?
+        )
+    }
+}
+
 // old code for instantly inserting tokens into database
 // should be moved out of here
 
@@ -0,0 +1,79 @@
+//! # Time-based one-time passwords (RFC 6238)
+//! Used for two-factor authentication with apps like Google Authenticator.
+//! Codes are 6 digits long, change every 30 seconds and are generated using HMAC-SHA1.
+
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use sha1::Sha1;
+
+const SECRET_LENGTH: usize = 20;
+const DIGITS: u32 = 6;
+const PERIOD: i64 = 30;
+/// Codes from previous and next periods are accepted as well,
+/// because clocks of the server and the phone could differ
+const SKEW: i64 = 1;
+
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0; SECRET_LENGTH];
+    rand::thread_rng().fill_bytes(&mut secret);
+    secret
+}
+
+/// Secret in the form users can type into the app manually
+pub fn encode_secret(secret: &[u8]) -> String {
+    BASE32_NOPAD.encode(secret)
+}
+
+/// URI which is usually shown as a QR code
+pub fn uri(secret: &[u8], issuer: &str, account: &str) -> String {
+    let issuer = urlencoding::encode(issuer);
+    let account = urlencoding::encode(account);
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={PERIOD}",
+        encode_secret(secret)
+    )
+}
+
+fn code_at(secret: &[u8], step: i64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([hash[offset], hash[offset + 1], hash[offset + 2], hash[offset + 3]])
+        & 0x7fff_ffff;
+    binary % 10_u32.pow(DIGITS)
+}
+
+/// Checks the code at the given unix timestamp.
+/// Returns the time step of the matched code, so it can be remembered to reject reusing it.
+pub fn verify(secret: &[u8], code: &str, timestamp: i64) -> Option<i64> {
+    if code.len() != DIGITS as usize {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+
+    let step = timestamp / PERIOD;
+    (step - SKEW..=step + SKEW).find(|&step| code_at(secret, step) == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_totp() {
+        // test vectors from RFC 6238, truncated to 6 digits
+        let secret = b"12345678901234567890";
+        assert_eq!(code_at(secret, 59 / PERIOD), 287082);
+        assert_eq!(code_at(secret, 1111111109 / PERIOD), 81804);
+        assert_eq!(code_at(secret, 2000000000 / PERIOD), 279037);
+
+        assert_eq!(verify(secret, "287082", 59), Some(1));
+        assert_eq!(verify(secret, "287082", 59 + PERIOD), Some(1));
+        assert_eq!(verify(secret, "287082", 59 + PERIOD * 3), None);
+        assert_eq!(verify(secret, "081804", 1111111109), Some(1111111109 / PERIOD));
+        assert_eq!(verify(secret, "81804", 1111111109), None);
+    }
+}